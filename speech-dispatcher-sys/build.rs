@@ -1,13 +1,113 @@
-extern crate bindgen;
-
 use std::env;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-fn main() {
-    println!("cargo:rustc-link-lib=speechd");
-    let out_dir = env::var("OUT_DIR").unwrap();
-    let _ = bindgen::builder()
-        .header("wrapper.h")
+/// The bindings in `src/bindings`, oldest first, with the libspeechd
+/// release they were generated from. Each enables the cargo feature of the
+/// same name.
+#[cfg(not(feature = "bindgen"))]
+const SHIPPED: &[((u32, u32), &str)] = &[((0, 8), "0_8"), ((0, 10), "0_10"), ((0, 11), "0_11")];
+
+/// Where the headers and library were found.
+struct Library {
+    #[cfg_attr(not(feature = "bindgen"), allow(dead_code))]
+    include_paths: Vec<PathBuf>,
+    version: Option<(u32, u32)>,
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim().split('.').map(str::parse);
+    match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Some((major, minor)),
+        _ => None,
+    }
+}
+
+/// Locates libspeechd. Setting `SPEECHD_INCLUDE_DIR` and `SPEECHD_LIB_DIR`
+/// skips pkg-config, which helps cross builds without a target pkg-config;
+/// `SPEECHD_VERSION` then tells which bindings to use. Otherwise the usual
+/// `PKG_CONFIG_PATH`, `PKG_CONFIG_SYSROOT_DIR` and `PKG_CONFIG_ALLOW_CROSS`
+/// variables apply.
+///
+/// With the `dynamic` feature nothing is linked, and a missing library only
+/// means the oldest shipped bindings are used.
+fn find_library() -> Library {
+    let dynamic = cfg!(feature = "dynamic");
+    println!("cargo:rerun-if-env-changed=SPEECHD_INCLUDE_DIR");
+    println!("cargo:rerun-if-env-changed=SPEECHD_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=SPEECHD_VERSION");
+    let include_dir = env::var_os("SPEECHD_INCLUDE_DIR");
+    let lib_dir = env::var_os("SPEECHD_LIB_DIR");
+    if include_dir.is_some() || lib_dir.is_some() {
+        if !dynamic {
+            if let Some(dir) = lib_dir {
+                println!(
+                    "cargo:rustc-link-search=native={}",
+                    Path::new(&dir).display()
+                );
+            }
+            println!("cargo:rustc-link-lib=speechd");
+        }
+        let version = env::var("SPEECHD_VERSION").ok().map(|v| {
+            parse_version(&v)
+                .unwrap_or_else(|| panic!("SPEECHD_VERSION should look like 0.11, not {:?}", v))
+        });
+        return Library {
+            include_paths: include_dir.into_iter().map(PathBuf::from).collect(),
+            version,
+        };
+    }
+    match pkg_config::Config::new()
+        .cargo_metadata(!dynamic)
+        .probe("speech-dispatcher")
+    {
+        Ok(library) => Library {
+            include_paths: library.include_paths,
+            version: parse_version(&library.version),
+        },
+        Err(_) if dynamic => Library {
+            include_paths: Vec::new(),
+            version: None,
+        },
+        Err(e) => panic!(
+            "\n\nCould not find libspeechd through pkg-config:\n\n{}\n\n\
+             Install the speech-dispatcher development package (libspeechd-dev \
+             on Debian and Ubuntu, speech-dispatcher-devel on Fedora), add the \
+             directory holding speech-dispatcher.pc to PKG_CONFIG_PATH, or set \
+             SPEECHD_INCLUDE_DIR and SPEECHD_LIB_DIR.\n\n",
+            e
+        ),
+    }
+}
+
+/// The shipped bindings to use: the newest whose feature is enabled, or else
+/// the newest the installed library is compatible with, or else the oldest.
+#[cfg(not(feature = "bindgen"))]
+fn select(library: &Library) -> &'static str {
+    let enabled = SHIPPED
+        .iter()
+        .rev()
+        .find(|(_, name)| env::var_os(format!("CARGO_FEATURE_{}", name)).is_some());
+    let detected = library.version.and_then(|version| {
+        SHIPPED
+            .iter()
+            .rev()
+            .find(|(shipped, _)| *shipped <= version)
+    });
+    enabled.or(detected).unwrap_or(&SHIPPED[0]).1
+}
+
+fn shipped_path(name: &str) -> PathBuf {
+    Path::new("src/bindings").join(format!("libspeechd_{}.rs", name))
+}
+
+#[cfg(feature = "bindgen")]
+fn generate(library: &Library) -> String {
+    let mut builder = bindgen::builder().header("wrapper.h");
+    for path in &library.include_paths {
+        builder = builder.clang_arg(format!("-I{}", path.display()));
+    }
+    builder
         .constified_enum_module("SPDConnectionMode")
         .constified_enum_module("SPDPriority")
         .constified_enum_module("SPDVoiceType")
@@ -19,6 +119,141 @@ fn main() {
         .use_core()
         .layout_tests(false)
         .generate()
-        .unwrap()
-        .write_to_file(Path::new(&out_dir).join("speech_dispatcher_sys.rs"));
+        .expect("Unable to generate bindings for libspeechd")
+        .to_string()
+}
+
+/// With `SPEECHD_CHECK_BINDINGS` set, fails the build if the shipped
+/// bindings for the installed release differ from freshly generated ones.
+/// With `SPEECHD_UPDATE_BINDINGS` set, overwrites them instead.
+#[cfg(feature = "bindgen")]
+fn check(library: &Library, generated: &str) {
+    println!("cargo:rerun-if-env-changed=SPEECHD_CHECK_BINDINGS");
+    println!("cargo:rerun-if-env-changed=SPEECHD_UPDATE_BINDINGS");
+    let checking = env::var_os("SPEECHD_CHECK_BINDINGS").is_some();
+    let updating = env::var_os("SPEECHD_UPDATE_BINDINGS").is_some();
+    if !checking && !updating {
+        return;
+    }
+    let (major, minor) = library
+        .version
+        .expect("Checking the bindings needs the libspeechd version");
+    let path = shipped_path(&format!("{}_{}", major, minor));
+    if updating {
+        fs::write(&path, generated).expect("Unable to write bindings");
+        return;
+    }
+    // bindgen is pinned to the release that generated the shipped files, so
+    // any difference comes from the headers.
+    let shipped = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("No shipped bindings at {}: {}", path.display(), e));
+    if shipped != generated {
+        panic!(
+            "\n\n{} no longer matches the installed headers. Rebuild with \
+             SPEECHD_UPDATE_BINDINGS=1 and --features bindgen to regenerate it.\n\n",
+            path.display()
+        );
+    }
+}
+
+/// A wrapper that resolves `item`, a function from an `extern` block, in
+/// the loaded library on first use. Variadic functions are called without
+/// their variable arguments.
+#[cfg(feature = "dynamic")]
+fn wrapper(item: syn::ForeignItemFn) -> proc_macro2::TokenStream {
+    use quote::quote;
+
+    let syn::ForeignItemFn {
+        attrs,
+        vis,
+        mut sig,
+        ..
+    } = item;
+    // bindgen's `link_name` attributes only apply inside `extern` blocks.
+    let docs = attrs.iter().filter(|attr| attr.path().is_ident("doc"));
+    let symbol = format!("{}\0", sig.ident);
+    let (names, types): (Vec<_>, Vec<_>) = sig
+        .inputs
+        .iter()
+        .map(|arg| match arg {
+            syn::FnArg::Typed(arg) => (&arg.pat, &arg.ty),
+            syn::FnArg::Receiver(_) => unreachable!("extern functions take no self"),
+        })
+        .unzip();
+    let variadic = sig.variadic.as_ref().map(|_| quote!(...));
+    let output = &sig.output;
+    let pointer = quote!(unsafe extern "C" fn(#(#types,)* #variadic) #output);
+    let call = quote!(f(#(#names),*));
+    sig.variadic = None;
+    sig.unsafety = Some(Default::default());
+    quote! {
+        #(#docs)*
+        #vis #sig {
+            static SYMBOL: crate::dynamic::Symbol = crate::dynamic::Symbol::new(#symbol);
+            let f = ::core::mem::transmute::<*mut ::core::ffi::c_void, #pointer>(
+                SYMBOL.get_or_panic(),
+            );
+            #call
+        }
+    }
+}
+
+/// Replaces the `extern` blocks of `bindings` with wrappers that call into
+/// the library loaded at runtime.
+#[cfg(feature = "dynamic")]
+fn dynamic(bindings: &str) -> String {
+    use quote::quote;
+
+    let file = syn::parse_file(bindings).expect("Unable to parse the bindings");
+    let mut items = Vec::new();
+    for item in file.items {
+        match item {
+            syn::Item::ForeignMod(block) => {
+                for item in block.items {
+                    match item {
+                        syn::ForeignItem::Fn(item) => items.push(wrapper(item)),
+                        item => panic!("Can't load {} at runtime", quote!(#item)),
+                    }
+                }
+            }
+            item => items.push(quote!(#item)),
+        }
+    }
+    let attrs = file.attrs;
+    quote!(#(#attrs)* #(#items)*).to_string()
+}
+
+fn main() {
+    let library = find_library();
+    if let Some((major, minor)) = library.version {
+        println!("cargo:version={}.{}", major, minor);
+        // Only meaningful when linking; a loaded library may be another one.
+        if !cfg!(feature = "dynamic") {
+            println!(
+                "cargo:rustc-env=SPEECHD_LIBRARY_VERSION={}.{}",
+                major, minor
+            );
+        }
+    }
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("speech_dispatcher_sys.rs");
+
+    #[cfg(feature = "bindgen")]
+    let bindings = {
+        let generated = generate(&library);
+        check(&library, &generated);
+        generated
+    };
+
+    #[cfg(not(feature = "bindgen"))]
+    let bindings = {
+        let path = shipped_path(select(&library));
+        println!("cargo:rerun-if-changed={}", path.display());
+        fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Unable to read {}: {}", path.display(), e))
+    };
+
+    #[cfg(feature = "dynamic")]
+    let bindings = dynamic(&bindings);
+
+    fs::write(&out, bindings).expect("Unable to write bindings");
 }
@@ -0,0 +1,580 @@
+/* automatically generated by rust-bindgen 0.69.4 */
+
+pub const SPEECHD_DEFAULT_PORT: u32 = 6560;
+pub const SPD_WAIT_REPLY: u32 = 1;
+pub const SPD_NO_REPLY: u32 = 0;
+pub const LIBSPEECHD_MAJOR_VERSION: u32 = 0;
+pub const LIBSPEECHD_MINOR_VERSION: u32 = 11;
+pub const LIBSPEECHD_MICRO_VERSION: u32 = 4;
+pub type wchar_t = ::core::ffi::c_int;
+pub type FILE = _IO_FILE;
+#[repr(C)]
+#[repr(align(8))]
+#[derive(Debug, Copy, Clone)]
+pub struct _IO_FILE {
+    pub _bindgen_opaque_blob: [u64; 27usize],
+}
+#[repr(C)]
+#[repr(align(8))]
+#[derive(Copy, Clone)]
+pub struct pthread_mutex_t {
+    pub _bindgen_opaque_blob: [u64; 5usize],
+}
+pub mod SPDPunctuation {
+    pub type Type = ::core::ffi::c_uint;
+    pub const SPD_PUNCT_ALL: Type = 0;
+    pub const SPD_PUNCT_NONE: Type = 1;
+    pub const SPD_PUNCT_SOME: Type = 2;
+    pub const SPD_PUNCT_MOST: Type = 3;
+}
+pub mod SPDCapitalLetters {
+    pub type Type = ::core::ffi::c_uint;
+    pub const SPD_CAP_NONE: Type = 0;
+    pub const SPD_CAP_SPELL: Type = 1;
+    pub const SPD_CAP_ICON: Type = 2;
+}
+pub mod SPDSpelling {
+    pub type Type = ::core::ffi::c_uint;
+    pub const SPD_SPELL_OFF: Type = 0;
+    pub const SPD_SPELL_ON: Type = 1;
+}
+pub mod SPDVoiceType {
+    pub type Type = ::core::ffi::c_int;
+    pub const SPD_MALE1: Type = 1;
+    pub const SPD_MALE2: Type = 2;
+    pub const SPD_MALE3: Type = 3;
+    pub const SPD_FEMALE1: Type = 4;
+    pub const SPD_FEMALE2: Type = 5;
+    pub const SPD_FEMALE3: Type = 6;
+    pub const SPD_CHILD_MALE: Type = 7;
+    pub const SPD_CHILD_FEMALE: Type = 8;
+    pub const SPD_UNSPECIFIED: Type = -1;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SPDVoice {
+    pub name: *mut ::core::ffi::c_char,
+    pub language: *mut ::core::ffi::c_char,
+    pub variant: *mut ::core::ffi::c_char,
+}
+pub mod SPDDataMode {
+    pub type Type = ::core::ffi::c_uint;
+    pub const SPD_DATA_TEXT: Type = 0;
+    pub const SPD_DATA_SSML: Type = 1;
+}
+pub mod SPDPriority {
+    pub type Type = ::core::ffi::c_uint;
+    pub const SPD_IMPORTANT: Type = 1;
+    pub const SPD_MESSAGE: Type = 2;
+    pub const SPD_TEXT: Type = 3;
+    pub const SPD_NOTIFICATION: Type = 4;
+    pub const SPD_PROGRESS: Type = 5;
+}
+pub mod SPDNotification {
+    pub type Type = ::core::ffi::c_uint;
+    pub const SPD_BEGIN: Type = 1;
+    pub const SPD_END: Type = 2;
+    pub const SPD_INDEX_MARKS: Type = 4;
+    pub const SPD_CANCEL: Type = 8;
+    pub const SPD_PAUSE: Type = 16;
+    pub const SPD_RESUME: Type = 32;
+    pub const SPD_ALL: Type = 63;
+}
+pub const SPDNotificationType_SPD_EVENT_BEGIN: SPDNotificationType = 0;
+pub const SPDNotificationType_SPD_EVENT_END: SPDNotificationType = 1;
+pub const SPDNotificationType_SPD_EVENT_INDEX_MARK: SPDNotificationType = 2;
+pub const SPDNotificationType_SPD_EVENT_CANCEL: SPDNotificationType = 3;
+pub const SPDNotificationType_SPD_EVENT_PAUSE: SPDNotificationType = 4;
+pub const SPDNotificationType_SPD_EVENT_RESUME: SPDNotificationType = 5;
+pub type SPDNotificationType = ::core::ffi::c_uint;
+pub mod SPDConnectionMode {
+    pub type Type = ::core::ffi::c_uint;
+    pub const SPD_MODE_SINGLE: Type = 0;
+    pub const SPD_MODE_THREADED: Type = 1;
+}
+pub const SPDConnectionMethod_SPD_METHOD_UNIX_SOCKET: SPDConnectionMethod = 0;
+pub const SPDConnectionMethod_SPD_METHOD_INET_SOCKET: SPDConnectionMethod = 1;
+pub type SPDConnectionMethod = ::core::ffi::c_uint;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SPDConnectionAddress {
+    pub method: SPDConnectionMethod,
+    pub unix_socket_name: *mut ::core::ffi::c_char,
+    pub inet_socket_host: *mut ::core::ffi::c_char,
+    pub inet_socket_port: ::core::ffi::c_int,
+    pub dbus_bus: *mut ::core::ffi::c_char,
+}
+extern "C" {
+    pub fn SPDConnectionAddress__free(address: *mut SPDConnectionAddress);
+}
+extern "C" {
+    pub fn spd_get_default_address(
+        error: *mut *mut ::core::ffi::c_char,
+    ) -> *mut SPDConnectionAddress;
+}
+pub type SPDCallback = ::core::option::Option<
+    unsafe extern "C" fn(msg_id: usize, client_id: usize, state: SPDNotificationType),
+>;
+pub type SPDCallbackIM = ::core::option::Option<
+    unsafe extern "C" fn(
+        msg_id: usize,
+        client_id: usize,
+        state: SPDNotificationType,
+        index_mark: *mut ::core::ffi::c_char,
+    ),
+>;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SPDConnection_threaddata {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SPDConnection {
+    pub callback_begin: SPDCallback,
+    pub callback_end: SPDCallback,
+    pub callback_cancel: SPDCallback,
+    pub callback_pause: SPDCallback,
+    pub callback_resume: SPDCallback,
+    pub callback_im: SPDCallbackIM,
+    pub socket: ::core::ffi::c_int,
+    pub stream: *mut FILE,
+    pub mode: SPDConnectionMode::Type,
+    pub ssip_mutex: *mut pthread_mutex_t,
+    pub td: *mut SPDConnection_threaddata,
+    pub reply: *mut ::core::ffi::c_char,
+}
+extern "C" {
+    pub fn spd_open(
+        client_name: *const ::core::ffi::c_char,
+        connection_name: *const ::core::ffi::c_char,
+        user_name: *const ::core::ffi::c_char,
+        mode: SPDConnectionMode::Type,
+    ) -> *mut SPDConnection;
+}
+extern "C" {
+    pub fn spd_open2(
+        client_name: *const ::core::ffi::c_char,
+        connection_name: *const ::core::ffi::c_char,
+        user_name: *const ::core::ffi::c_char,
+        mode: SPDConnectionMode::Type,
+        address: *const SPDConnectionAddress,
+        autospawn: ::core::ffi::c_int,
+        error_result: *mut *mut ::core::ffi::c_char,
+    ) -> *mut SPDConnection;
+}
+extern "C" {
+    pub fn spd_close(connection: *mut SPDConnection);
+}
+extern "C" {
+    pub fn spd_say(
+        connection: *mut SPDConnection,
+        priority: SPDPriority::Type,
+        text: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_sayf(
+        connection: *mut SPDConnection,
+        priority: SPDPriority::Type,
+        format: *const ::core::ffi::c_char,
+        ...
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_stop(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_stop_all(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_stop_uid(
+        connection: *mut SPDConnection,
+        target_uid: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_cancel(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_cancel_all(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_cancel_uid(
+        connection: *mut SPDConnection,
+        target_uid: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_pause(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_pause_all(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_pause_uid(
+        connection: *mut SPDConnection,
+        target_uid: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_resume(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_resume_all(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_resume_uid(
+        connection: *mut SPDConnection,
+        target_uid: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_key(
+        connection: *mut SPDConnection,
+        priority: SPDPriority::Type,
+        key_name: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_char(
+        connection: *mut SPDConnection,
+        priority: SPDPriority::Type,
+        character: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_wchar(
+        connection: *mut SPDConnection,
+        priority: SPDPriority::Type,
+        wcharacter: wchar_t,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_sound_icon(
+        connection: *mut SPDConnection,
+        priority: SPDPriority::Type,
+        icon_name: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_type(
+        connection: *mut SPDConnection,
+        type_: SPDVoiceType::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_type_all(
+        connection: *mut SPDConnection,
+        type_: SPDVoiceType::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_type_uid(
+        connection: *mut SPDConnection,
+        type_: SPDVoiceType::Type,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_get_voice_type(connection: *mut SPDConnection) -> SPDVoiceType::Type;
+}
+extern "C" {
+    pub fn spd_set_synthesis_voice(
+        connection: *mut SPDConnection,
+        voice_name: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_synthesis_voice_all(
+        connection: *mut SPDConnection,
+        voice_name: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_synthesis_voice_uid(
+        connection: *mut SPDConnection,
+        voice_name: *const ::core::ffi::c_char,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_data_mode(
+        connection: *mut SPDConnection,
+        mode: SPDDataMode::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_notification_on(
+        connection: *mut SPDConnection,
+        notification: SPDNotification::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_notification_off(
+        connection: *mut SPDConnection,
+        notification: SPDNotification::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_notification(
+        connection: *mut SPDConnection,
+        notification: SPDNotification::Type,
+        state: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_rate(
+        connection: *mut SPDConnection,
+        rate: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_rate_all(
+        connection: *mut SPDConnection,
+        rate: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_rate_uid(
+        connection: *mut SPDConnection,
+        rate: ::core::ffi::c_int,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_get_voice_rate(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_pitch(
+        connection: *mut SPDConnection,
+        pitch: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_pitch_all(
+        connection: *mut SPDConnection,
+        pitch: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_pitch_uid(
+        connection: *mut SPDConnection,
+        pitch: ::core::ffi::c_int,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_get_voice_pitch(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_pitch_range(
+        connection: *mut SPDConnection,
+        pitch_range: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_pitch_range_all(
+        connection: *mut SPDConnection,
+        pitch_range: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_voice_pitch_range_uid(
+        connection: *mut SPDConnection,
+        pitch_range: ::core::ffi::c_int,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_volume(
+        connection: *mut SPDConnection,
+        volume: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_volume_all(
+        connection: *mut SPDConnection,
+        volume: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_volume_uid(
+        connection: *mut SPDConnection,
+        volume: ::core::ffi::c_int,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_get_volume(connection: *mut SPDConnection) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_punctuation(
+        connection: *mut SPDConnection,
+        type_: SPDPunctuation::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_punctuation_all(
+        connection: *mut SPDConnection,
+        type_: SPDPunctuation::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_punctuation_uid(
+        connection: *mut SPDConnection,
+        type_: SPDPunctuation::Type,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_capital_letters(
+        connection: *mut SPDConnection,
+        type_: SPDCapitalLetters::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_capital_letters_all(
+        connection: *mut SPDConnection,
+        type_: SPDCapitalLetters::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_capital_letters_uid(
+        connection: *mut SPDConnection,
+        type_: SPDCapitalLetters::Type,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_spelling(
+        connection: *mut SPDConnection,
+        type_: SPDSpelling::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_spelling_all(
+        connection: *mut SPDConnection,
+        type_: SPDSpelling::Type,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_spelling_uid(
+        connection: *mut SPDConnection,
+        type_: SPDSpelling::Type,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_language(
+        connection: *mut SPDConnection,
+        language: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_language_all(
+        connection: *mut SPDConnection,
+        language: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_language_uid(
+        connection: *mut SPDConnection,
+        language: *const ::core::ffi::c_char,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_get_language(connection: *mut SPDConnection) -> *mut ::core::ffi::c_char;
+}
+extern "C" {
+    pub fn spd_set_output_module(
+        connection: *mut SPDConnection,
+        output_module: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_output_module_all(
+        connection: *mut SPDConnection,
+        output_module: *const ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_set_output_module_uid(
+        connection: *mut SPDConnection,
+        output_module: *const ::core::ffi::c_char,
+        uid: ::core::ffi::c_uint,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_get_message_list_fd(
+        connection: *mut SPDConnection,
+        target: ::core::ffi::c_int,
+        msg_ids: *mut ::core::ffi::c_int,
+        client_names: *mut *mut ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_list_modules(connection: *mut SPDConnection) -> *mut *mut ::core::ffi::c_char;
+}
+extern "C" {
+    pub fn free_spd_modules(arg1: *mut *mut ::core::ffi::c_char);
+}
+extern "C" {
+    pub fn spd_get_output_module(connection: *mut SPDConnection) -> *mut ::core::ffi::c_char;
+}
+extern "C" {
+    pub fn spd_list_voices(connection: *mut SPDConnection) -> *mut *mut ::core::ffi::c_char;
+}
+extern "C" {
+    pub fn spd_list_synthesis_voices(connection: *mut SPDConnection) -> *mut *mut SPDVoice;
+}
+extern "C" {
+    pub fn spd_list_synthesis_voices2(
+        connection: *mut SPDConnection,
+        language: *const ::core::ffi::c_char,
+        variant: *const ::core::ffi::c_char,
+    ) -> *mut *mut SPDVoice;
+}
+extern "C" {
+    pub fn free_spd_voices(voices: *mut *mut SPDVoice);
+}
+extern "C" {
+    pub fn spd_execute_command_with_list_reply(
+        connection: *mut SPDConnection,
+        command: *mut ::core::ffi::c_char,
+    ) -> *mut *mut ::core::ffi::c_char;
+}
+extern "C" {
+    pub fn spd_execute_command(
+        connection: *mut SPDConnection,
+        command: *mut ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_execute_command_with_reply(
+        connection: *mut SPDConnection,
+        command: *mut ::core::ffi::c_char,
+        reply: *mut *mut ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_execute_command_wo_mutex(
+        connection: *mut SPDConnection,
+        command: *mut ::core::ffi::c_char,
+    ) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn spd_send_data(
+        connection: *mut SPDConnection,
+        message: *const ::core::ffi::c_char,
+        wfr: ::core::ffi::c_int,
+    ) -> *mut ::core::ffi::c_char;
+}
+extern "C" {
+    pub fn spd_send_data_wo_mutex(
+        connection: *mut SPDConnection,
+        message: *const ::core::ffi::c_char,
+        wfr: ::core::ffi::c_int,
+    ) -> *mut ::core::ffi::c_char;
+}
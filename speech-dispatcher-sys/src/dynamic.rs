@@ -0,0 +1,81 @@
+//! Loading libspeechd at runtime, for the `dynamic` feature.
+
+use core::ffi::c_void;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use libloading::Library;
+
+/// The file loaded, found through the usual search path.
+pub const LIBRARY_NAME: &str = "libspeechd.so.2";
+
+static LIBRARY: AtomicPtr<Library> = AtomicPtr::new(ptr::null_mut());
+
+/// Loads libspeechd unless it is already loaded. The functions of this crate
+/// load it themselves but panic if that fails, so call this first when the
+/// library may be missing. Failures are not cached.
+pub fn load() -> Result<&'static Library, libloading::Error> {
+    let loaded = LIBRARY.load(Ordering::Acquire);
+    if !loaded.is_null() {
+        return Ok(unsafe { &*loaded });
+    }
+    let library = Box::into_raw(Box::new(unsafe { Library::new(LIBRARY_NAME)? }));
+    match LIBRARY.compare_exchange(
+        ptr::null_mut(),
+        library,
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    ) {
+        Ok(_) => Ok(unsafe { &*library }),
+        Err(loaded) => {
+            drop(unsafe { Box::from_raw(library) });
+            Ok(unsafe { &*loaded })
+        }
+    }
+}
+
+/// A function of the library, resolved on first use.
+#[doc(hidden)]
+pub struct Symbol {
+    name: &'static str,
+    address: AtomicPtr<c_void>,
+}
+
+impl Symbol {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            address: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// The address of the function, loading the library first if needed.
+    pub fn get(&self) -> Result<*mut c_void, libloading::Error> {
+        let address = self.address.load(Ordering::Acquire);
+        if !address.is_null() {
+            return Ok(address);
+        }
+        let address = *unsafe { load()?.get::<*mut c_void>(self.name.as_bytes())? };
+        self.address.store(address, Ordering::Release);
+        Ok(address)
+    }
+
+    /// Like `get`, for the generated wrappers, which have no way to report
+    /// an error.
+    pub fn get_or_panic(&self) -> *mut c_void {
+        self.get().unwrap_or_else(|e| {
+            let name = self.name.trim_end_matches('\0');
+            panic!("Unable to call {} in {}: {}", name, LIBRARY_NAME, e)
+        })
+    }
+}
+
+/// Whether libspeechd can be loaded and has the function `name`. Functions
+/// added in later releases panic when called on an older library, so check
+/// for them first.
+pub fn has_symbol(name: &str) -> bool {
+    match load() {
+        Ok(library) => unsafe { library.get::<*mut c_void>(name.as_bytes()).is_ok() },
+        Err(_) => false,
+    }
+}
@@ -1,7 +1,18 @@
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
+#![cfg_attr(feature = "dynamic", allow(clippy::missing_safety_doc))]
 
 extern crate core;
 
+#[cfg(feature = "dynamic")]
+mod dynamic;
+#[cfg(feature = "dynamic")]
+pub use dynamic::{has_symbol, load, LIBRARY_NAME};
+
+/// The `major.minor` version of the libspeechd linked in, as pkg-config or
+/// `SPEECHD_VERSION` reported it at build time. `None` when it wasn't known,
+/// and always with the `dynamic` feature, which links nothing.
+pub const LINKED_VERSION: Option<&str> = option_env!("SPEECHD_LIBRARY_VERSION");
+
 include!(concat!(env!("OUT_DIR"), "/speech_dispatcher_sys.rs"));
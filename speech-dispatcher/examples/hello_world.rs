@@ -7,7 +7,8 @@ fn main() {
         "hello_world",
         "hello_world",
         Mode::Threaded,
-    );
+    )
+    .expect("Failed to connect to speech-dispatcher");
     connection.on_begin(Some(Box::new(|msg_id, client_id| {
         println!("Beginning {} from {}", msg_id, client_id)
     })));
@@ -25,9 +26,11 @@ fn main() {
     connection.set_voice_rate(100);
     connection.say(Priority::Important, "This is faster.");
     connection.set_voice_rate(0);
-    connection.set_spelling(true);
-    connection.say(Priority::Important, "This is spelled.");
-    connection.set_spelling(false);
+    {
+        let mut spelled = connection.scoped();
+        spelled.set_spelling(true).unwrap();
+        spelled.say(Priority::Important, "This is spelled.");
+    }
     connection.set_punctuation(Punctuation::All);
     connection.say(
         Priority::Important,
@@ -0,0 +1,317 @@
+//! Where to find the server, in the syntax of `SPEECHD_ADDRESS`.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use speech_dispatcher_sys::SPEECHD_DEFAULT_PORT;
+
+/// A server address, written `unix_socket:/path/to/socket`,
+/// `inet_socket:host`, `inet_socket:host:port` or, for IPv6 literals,
+/// `inet_socket:[::1]:6560`.
+///
+/// libspeechd can't connect over IPv6, so `Connection::open_address` fails
+/// with `Error::Unsupported(Feature::Ipv6)` for such addresses. `connect`
+/// reaches them, for use with `AsyncConnection`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    Unix(PathBuf),
+    Inet { host: String, port: u16 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddressParseError(String);
+
+impl fmt::Display for AddressParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid speech-dispatcher address: {}", self.0)
+    }
+}
+
+impl std::error::Error for AddressParseError {}
+
+impl Address {
+    pub fn inet<S: Into<String>>(host: S) -> Self {
+        Address::Inet {
+            host: host.into(),
+            port: SPEECHD_DEFAULT_PORT as u16,
+        }
+    }
+
+    /// The address libspeechd connects to by default, from
+    /// `SPEECHD_ADDRESS` or else the user's runtime directory. Needs
+    /// libspeechd 0.11, and fails with `Error::Unsupported` without it. When
+    /// libspeechd finds no address the error is `NotFound` with its reason.
+    #[cfg(feature = "0_11")]
+    pub fn default_address() -> Result<Self, crate::Error> {
+        use speech_dispatcher_sys::*;
+        use std::ffi::CStr;
+
+        crate::Feature::DefaultAddress.require()?;
+
+        let mut error = std::ptr::null_mut();
+        unsafe {
+            let raw = spd_get_default_address(&mut error);
+            if raw.is_null() {
+                let why = if error.is_null() {
+                    "no default address".to_string()
+                } else {
+                    let why = CStr::from_ptr(error).to_string_lossy().into_owned();
+                    libc::free(error as *mut libc::c_void);
+                    why
+                };
+                return Err(io::Error::new(io::ErrorKind::NotFound, why).into());
+            }
+            let string = |s: *mut std::os::raw::c_char| {
+                if s.is_null() {
+                    String::new()
+                } else {
+                    CStr::from_ptr(s).to_string_lossy().into_owned()
+                }
+            };
+            let address = &*raw;
+            let result = match address.method {
+                SPDConnectionMethod_SPD_METHOD_UNIX_SOCKET => Ok(Address::Unix(PathBuf::from(
+                    string(address.unix_socket_name),
+                ))),
+                SPDConnectionMethod_SPD_METHOD_INET_SOCKET => Ok(Address::Inet {
+                    host: string(address.inet_socket_host),
+                    port: address.inet_socket_port as u16,
+                }),
+                method => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported connection method {}", method),
+                )
+                .into()),
+            };
+            SPDConnectionAddress__free(raw);
+            result
+        }
+    }
+
+    /// Looks up the socket addresses of an inet address. Hostnames go through
+    /// the system resolver.
+    pub fn resolve(&self) -> io::Result<Vec<SocketAddr>> {
+        match self {
+            Address::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unix socket addresses don't resolve to IP addresses",
+            )),
+            Address::Inet { host, port } => match host.parse::<IpAddr>() {
+                Ok(ip) => Ok(vec![SocketAddr::new(ip, *port)]),
+                Err(_) => Ok((host.as_str(), *port).to_socket_addrs()?.collect()),
+            },
+        }
+    }
+
+    /// Connects to the server, trying each resolved address in turn and
+    /// giving each up to `timeout`. The stream can be handed to
+    /// `AsyncConnection` through the runtime's wrapper for std sockets.
+    pub fn connect(&self, timeout: Option<Duration>) -> io::Result<Stream> {
+        match self {
+            Address::Unix(path) => UnixStream::connect(path).map(Stream::Unix),
+            Address::Inet { .. } => {
+                let mut last_err = None;
+                for addr in self.resolve()? {
+                    let stream = match timeout {
+                        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                        None => TcpStream::connect(addr),
+                    };
+                    match stream {
+                        Ok(stream) => return Ok(Stream::Tcp(stream)),
+                        Err(e) => last_err = Some(e),
+                    }
+                }
+                Err(last_err.unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "host has no addresses")
+                }))
+            }
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = |why: &str| AddressParseError(format!("{:?}: {}", s, why));
+        if let Some(path) = s.strip_prefix("unix_socket:") {
+            if path.is_empty() {
+                return Err(err("missing socket path"));
+            }
+            return Ok(Address::Unix(PathBuf::from(path)));
+        }
+        let rest = s
+            .strip_prefix("inet_socket:")
+            .ok_or_else(|| err("expected unix_socket: or inet_socket:"))?;
+        let (host, port) = if let Some(rest) = rest.strip_prefix('[') {
+            let end = rest.find(']').ok_or_else(|| err("unclosed '['"))?;
+            let host = &rest[..end];
+            if host.parse::<std::net::Ipv6Addr>().is_err() {
+                return Err(err("brackets must hold an IPv6 address"));
+            }
+            match &rest[end + 1..] {
+                "" => (host, None),
+                port => (
+                    host,
+                    Some(port.strip_prefix(':').ok_or_else(|| err("expected ':'"))?),
+                ),
+            }
+        } else if rest.matches(':').count() > 1 {
+            // A bare IPv6 literal, which can't carry a port.
+            (rest, None)
+        } else {
+            match rest.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (rest, None),
+            }
+        };
+        if host.is_empty() {
+            return Err(err("missing host"));
+        }
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| err("invalid port"))?,
+            None => SPEECHD_DEFAULT_PORT as u16,
+        };
+        Ok(Address::Inet {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Address::Unix(path) => write!(f, "unix_socket:{}", path.display()),
+            Address::Inet { host, port } if host.contains(':') => {
+                write!(f, "inet_socket:[{}]:{}", host, port)
+            }
+            Address::Inet { host, port } => write!(f, "inet_socket:{}:{}", host, port),
+        }
+    }
+}
+
+/// A connected socket to the server.
+#[derive(Debug)]
+pub enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(s) => s.read(buf),
+            Stream::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(s) => s.write(buf),
+            Stream::Tcp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Unix(s) => s.flush(),
+            Stream::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Stream::Unix(s) => s.as_raw_fd(),
+            Stream::Tcp(s) => s.as_raw_fd(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inet(host: &str, port: u16) -> Address {
+        Address::Inet {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    #[test]
+    fn parses_unix_and_inet_addresses() {
+        let default = SPEECHD_DEFAULT_PORT as u16;
+        assert_eq!(
+            "unix_socket:/run/user/1000/speech-dispatcher/speechd.sock".parse(),
+            Ok(Address::Unix(PathBuf::from(
+                "/run/user/1000/speech-dispatcher/speechd.sock"
+            )))
+        );
+        assert_eq!(
+            "inet_socket:localhost".parse(),
+            Ok(inet("localhost", default))
+        );
+        assert_eq!(
+            "inet_socket:10.0.0.2:7000".parse(),
+            Ok(inet("10.0.0.2", 7000))
+        );
+    }
+
+    #[test]
+    fn parses_ipv6_literals() {
+        let default = SPEECHD_DEFAULT_PORT as u16;
+        assert_eq!("inet_socket:[::1]:7000".parse(), Ok(inet("::1", 7000)));
+        assert_eq!(
+            "inet_socket:[fe80::2]".parse(),
+            Ok(inet("fe80::2", default))
+        );
+        assert_eq!("inet_socket:::1".parse(), Ok(inet("::1", default)));
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        for s in &[
+            "tcp:localhost",
+            "unix_socket:",
+            "inet_socket:",
+            "inet_socket::6560",
+            "inet_socket:host:port",
+            "inet_socket:host:70000",
+            "inet_socket:[::1",
+            "inet_socket:[localhost]:6560",
+            "inet_socket:[::1]6560",
+            "inet_socket:[::1]:",
+        ] {
+            assert!(s.parse::<Address>().is_err(), "{} parsed", s);
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in &[
+            "unix_socket:/tmp/speechd.sock",
+            "inet_socket:localhost:6560",
+            "inet_socket:[::1]:7000",
+        ] {
+            assert_eq!(s.parse::<Address>().unwrap().to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn resolves_ip_literals_without_lookup() {
+        let addrs = inet("::1", 7000).resolve().unwrap();
+        assert_eq!(addrs, ["[::1]:7000".parse().unwrap()]);
+        assert!(Address::Unix(PathBuf::from("/tmp/s")).resolve().is_err());
+    }
+}
@@ -0,0 +1,1266 @@
+//! An SSIP client that talks to the server over any `futures-io` stream, so
+//! it works the same under tokio (through its compat layer), smol, async-std
+//! or an in-memory pipe.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+use futures::{
+    future::poll_fn,
+    io::{AsyncRead, AsyncWrite},
+    lock::Mutex as CommandLock,
+    stream::Stream,
+};
+
+use crate::history::{self, ClientInfo, ClientSpec, CursorPosition, HistoryMessage};
+use crate::ssip::{self, Assembler, Message, Reply};
+use crate::ssml;
+use crate::voice;
+use crate::{
+    Capabilities, CapitalLetters, ClientId, ClientIdentity, DataMode, EnumParseError, Error, Event,
+    Key, LanguageTag, LanguageTagParseError, Notification, Priority, Punctuation, SoundIcon, Voice,
+    VoiceType,
+};
+
+fn wake(waker: &mut Option<Waker>) {
+    if let Some(waker) = waker.take() {
+        waker.wake();
+    }
+}
+
+fn closed() -> Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "speech-dispatcher closed the connection",
+    )
+    .into()
+}
+
+/// Replies and events share one socket. Whoever polls it, a command waiting
+/// for its reply or an `Events` stream, sorts what arrives into the right
+/// queue and wakes the others so one of them registers with the stream again.
+struct Io<S> {
+    stream: S,
+    buf: Vec<u8>,
+    assembler: Assembler,
+    /// Written before anything is read, so a command is never sent in part.
+    outbox: Vec<u8>,
+    replies: VecDeque<Reply>,
+    /// Replies owed to commands whose futures were dropped. They are
+    /// discarded as they arrive.
+    orphans: usize,
+    listeners: HashMap<u64, Listener>,
+    next_listener: u64,
+    reply_waker: Option<Waker>,
+    eof: bool,
+}
+
+/// The queue and waker of one `Events` stream.
+#[derive(Default)]
+struct Listener {
+    events: VecDeque<Event>,
+    waker: Option<Waker>,
+}
+
+impl<S> Io<S> {
+    fn wake_all(&mut self) {
+        wake(&mut self.reply_waker);
+        for listener in self.listeners.values_mut() {
+            wake(&mut listener.waker);
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Io<S> {
+    fn poll_fill(&mut self, cx: &mut Context) -> Poll<Result<(), Error>> {
+        if self.eof {
+            return Poll::Ready(Err(closed()));
+        }
+        let mut chunk = [0; 1024];
+        let n = match Pin::new(&mut self.stream).poll_read(cx, &mut chunk) {
+            Poll::Ready(Ok(n)) => n,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+            Poll::Pending => return Poll::Pending,
+        };
+        if n == 0 {
+            self.eof = true;
+            self.wake_all();
+            return Poll::Ready(Err(closed()));
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        while let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            match self
+                .assembler
+                .push(line.trim_end_matches(&['\r', '\n'][..]))?
+            {
+                Some(Message::Reply(_)) if self.orphans > 0 => self.orphans -= 1,
+                Some(Message::Reply(reply)) => {
+                    self.replies.push_back(reply);
+                    wake(&mut self.reply_waker);
+                }
+                Some(Message::Event(event)) => {
+                    for listener in self.listeners.values_mut() {
+                        listener.events.push_back(event.clone());
+                        wake(&mut listener.waker);
+                    }
+                }
+                None => {}
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_reply(&mut self, cx: &mut Context) -> Poll<Result<Reply, Error>> {
+        self.reply_waker = None;
+        loop {
+            if let Some(reply) = self.replies.pop_front() {
+                self.wake_all();
+                return Poll::Ready(Ok(reply));
+            }
+            match self.poll_fill(cx) {
+                Poll::Ready(Ok(())) => continue,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => {
+                    self.reply_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    fn poll_event(&mut self, id: u64, cx: &mut Context) -> Poll<Option<Event>> {
+        loop {
+            let listener = self.listeners.get_mut(&id).expect("listener registered");
+            listener.waker = None;
+            if let Some(event) = listener.events.pop_front() {
+                self.wake_all();
+                return Poll::Ready(Some(event));
+            }
+            match self.poll_fill(cx) {
+                Poll::Ready(Ok(())) => continue,
+                Poll::Ready(Err(_)) => return Poll::Ready(None),
+                Poll::Pending => {
+                    if let Some(listener) = self.listeners.get_mut(&id) {
+                        listener.waker = Some(cx.waker().clone());
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context) -> Poll<Result<(), Error>> {
+        while !self.outbox.is_empty() {
+            match Pin::new(&mut self.stream).poll_write(cx, &self.outbox) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::Error::from(io::ErrorKind::WriteZero).into()))
+                }
+                Poll::Ready(Ok(n)) => {
+                    self.outbox.drain(..n);
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Pin::new(&mut self.stream)
+            .poll_flush(cx)
+            .map_err(Error::from)
+    }
+}
+
+struct Shared<S> {
+    io: Mutex<Io<S>>,
+    /// Held for the duration of a command. It also tracks the data mode,
+    /// which `say_ssml` switches temporarily.
+    commands: CommandLock<DataMode>,
+    identity: Mutex<ClientIdentity>,
+}
+
+impl<S> Shared<S> {
+    fn io(&self) -> MutexGuard<'_, Io<S>> {
+        self.io.lock().unwrap()
+    }
+}
+
+/// What a command still expects from the server. If its future is dropped
+/// midway, the replies it was waiting for are discarded when they arrive and
+/// the `cleanup` commands are sent, newest first, to undo what it started.
+/// The next command then reads its own reply.
+struct Pending<'a, S> {
+    shared: &'a Shared<S>,
+    replies: usize,
+    cleanup: Vec<String>,
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> Pending<'a, S> {
+    fn new(shared: &'a Shared<S>) -> Self {
+        Self {
+            shared,
+            replies: 0,
+            cleanup: Vec::new(),
+        }
+    }
+
+    /// Queues data that the server answers with one reply.
+    fn queue(&mut self, data: &[u8]) {
+        self.shared.io().outbox.extend_from_slice(data);
+        self.replies += 1;
+    }
+
+    async fn reply(&mut self) -> Result<Reply, Error> {
+        poll_fn(|cx| self.shared.io().poll_flush(cx)).await?;
+        let reply = poll_fn(|cx| self.shared.io().poll_reply(cx)).await?;
+        self.replies -= 1;
+        Ok(reply)
+    }
+
+    async fn command(&mut self, command: &str) -> Result<Reply, Error> {
+        self.queue(format!("{}\r\n", command).as_bytes());
+        self.reply().await?.into_result()
+    }
+}
+
+impl<S> Drop for Pending<'_, S> {
+    fn drop(&mut self) {
+        if self.replies == 0 && self.cleanup.is_empty() {
+            return;
+        }
+        let mut io = self.shared.io();
+        let queued = self.replies.min(io.replies.len());
+        io.replies.drain(..queued);
+        io.orphans += self.replies - queued + self.cleanup.len();
+        for command in self.cleanup.iter().rev() {
+            io.outbox
+                .extend_from_slice(format!("{}\r\n", command).as_bytes());
+        }
+    }
+}
+
+/// The asynchronous counterpart of [`Connection`](crate::Connection).
+///
+/// Commands are sent one at a time; clones share the same stream.
+pub struct AsyncConnection<S> {
+    shared: Arc<Shared<S>>,
+    client_id: u64,
+}
+
+impl<S> Clone for AsyncConnection<S> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            client_id: self.client_id,
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncConnection<S> {
+    /// Identifies the client on an already connected stream, the way
+    /// `spd_open` does, and turns on all notifications.
+    pub async fn open<N: Into<String>>(
+        stream: S,
+        client_name: N,
+        connection_name: N,
+        user_name: N,
+    ) -> Result<Self, Error> {
+        let io = Io {
+            stream,
+            buf: Vec::new(),
+            assembler: Assembler::default(),
+            outbox: Vec::new(),
+            replies: VecDeque::new(),
+            orphans: 0,
+            listeners: HashMap::new(),
+            next_listener: 0,
+            reply_waker: None,
+            eof: false,
+        };
+        let identity =
+            ClientIdentity::unchecked(user_name.into(), client_name.into(), connection_name.into());
+        let mut connection = Self {
+            shared: Arc::new(Shared {
+                io: Mutex::new(io),
+                commands: CommandLock::new(DataMode::Text),
+                identity: Mutex::new(identity.clone()),
+            }),
+            client_id: 0,
+        };
+        connection
+            .execute(&format!("SET SELF CLIENT_NAME \"{}\"", identity))
+            .await?;
+        let reply = connection.execute("HISTORY GET CLIENT_ID").await?;
+        connection.client_id = reply
+            .value()?
+            .trim()
+            .parse()
+            .map_err(|_| Error::Protocol(format!("invalid client id {:?}", reply.value())))?;
+        connection.set_notification_on(Notification::All).await?;
+        Ok(connection)
+    }
+
+    /// A stream of the notifications sent to this client. Every stream
+    /// receives every event; events that arrive while no stream exists are
+    /// dropped.
+    pub fn events(&self) -> Events<S> {
+        let mut io = self.shared.io();
+        let id = io.next_listener;
+        io.next_listener += 1;
+        io.listeners.insert(id, Listener::default());
+        Events {
+            shared: self.shared.clone(),
+            id,
+        }
+    }
+
+    pub fn client_id(&self) -> ClientId {
+        ClientId(self.client_id)
+    }
+
+    /// The names this connection was opened with or last given by
+    /// `set_identity`.
+    pub fn identity(&self) -> ClientIdentity {
+        self.shared.identity.lock().unwrap().clone()
+    }
+
+    pub async fn set_identity(&self, identity: &ClientIdentity) -> Result<(), Error> {
+        let _lock = self.shared.commands.lock().await;
+        self.command(&format!("SET SELF CLIENT_NAME \"{}\"", identity))
+            .await?;
+        *self.shared.identity.lock().unwrap() = identity.clone();
+        Ok(())
+    }
+
+    /// Sends one command and waits for its reply. The caller must hold the
+    /// command lock.
+    async fn command(&self, command: &str) -> Result<Reply, Error> {
+        Pending::new(&self.shared).command(command).await
+    }
+
+    async fn execute(&self, command: &str) -> Result<Reply, Error> {
+        let _lock = self.shared.commands.lock().await;
+        self.command(command).await
+    }
+
+    async fn set<V: Display>(&self, target: &str, param: &str, value: V) -> Result<(), Error> {
+        self.execute(&format!("SET {} {} {}", target, param, value))
+            .await
+            .map(|_| ())
+    }
+
+    async fn get(&self, param: &str) -> Result<String, Error> {
+        let reply = self.execute(&format!("GET {}", param)).await?;
+        reply.value().map(String::from)
+    }
+
+    async fn get_number(&self, param: &str) -> Result<i32, Error> {
+        let value = self.get(param).await?;
+        value
+            .trim()
+            .parse()
+            .map_err(|_| Error::Protocol(format!("invalid {} {:?}", param, value)))
+    }
+
+    /// Sends a command that queues a message with the given priority.
+    async fn message(&self, priority: Priority, command: &str) -> Result<(), Error> {
+        let _lock = self.shared.commands.lock().await;
+        self.command(&format!("SET SELF PRIORITY {}", priority.as_ssip()))
+            .await?;
+        self.command(command).await.map(|_| ())
+    }
+
+    pub async fn close(&self) -> Result<(), Error> {
+        self.execute("QUIT").await?;
+        poll_fn(|cx| Pin::new(&mut self.shared.io().stream).poll_close(cx)).await?;
+        Ok(())
+    }
+
+    /// Queues a message. The caller must hold the command lock.
+    async fn speak(
+        &self,
+        pending: &mut Pending<'_, S>,
+        priority: Priority,
+        text: &str,
+    ) -> Result<u64, Error> {
+        let text = ssip::escape_message(text);
+        pending
+            .command(&format!("SET SELF PRIORITY {}", priority.as_ssip()))
+            .await?;
+        // Until the text is queued, the server may be waiting for it.
+        pending.cleanup.push(".".into());
+        let accepted = pending.command("SPEAK").await;
+        pending.cleanup.pop();
+        accepted?;
+        pending.queue(text.as_bytes());
+        let reply = pending.reply().await?.into_result()?;
+        let msg_id = reply.value()?;
+        msg_id
+            .trim()
+            .parse()
+            .map_err(|_| Error::Protocol(format!("invalid message id {:?}", msg_id)))
+    }
+
+    pub async fn say<T: Into<String>>(&self, priority: Priority, text: T) -> Result<u64, Error> {
+        let _lock = self.shared.commands.lock().await;
+        self.speak(&mut Pending::new(&self.shared), priority, &text.into())
+            .await
+    }
+
+    /// Speaks an SSML document with SSML mode turned on for this message
+    /// only. See [`Connection::say_ssml`](crate::Connection::say_ssml).
+    pub async fn say_ssml<T: Into<String>>(
+        &self,
+        priority: Priority,
+        doc: T,
+    ) -> Result<u64, Error> {
+        let doc = doc.into();
+        ssml::check(&doc)?;
+        let mode = self.shared.commands.lock().await;
+        let switch = !matches!(*mode, DataMode::SSML);
+        let restore = format!("SET SELF SSML_MODE {}", mode.ssml_mode());
+        let mut pending = Pending::new(&self.shared);
+        if switch {
+            pending.command("SET SELF SSML_MODE on").await?;
+            pending.cleanup.push(restore.clone());
+        }
+        let msg_id = self.speak(&mut pending, priority, &doc).await;
+        if switch {
+            pending.cleanup.pop();
+            let restored = pending.command(&restore).await;
+            if msg_id.is_ok() {
+                restored?;
+            }
+        }
+        msg_id
+    }
+
+    pub async fn stop(&self) -> Result<(), Error> {
+        self.execute("STOP SELF").await.map(|_| ())
+    }
+
+    pub async fn stop_all(&self) -> Result<(), Error> {
+        self.execute("STOP all").await.map(|_| ())
+    }
+
+    pub async fn stop_uid(&self, target_uid: i32) -> Result<(), Error> {
+        self.execute(&format!("STOP {}", target_uid))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn cancel(&self) -> Result<(), Error> {
+        self.execute("CANCEL SELF").await.map(|_| ())
+    }
+
+    pub async fn cancel_all(&self) -> Result<(), Error> {
+        self.execute("CANCEL all").await.map(|_| ())
+    }
+
+    pub async fn cancel_uid(&self, target_uid: i32) -> Result<(), Error> {
+        self.execute(&format!("CANCEL {}", target_uid))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn pause(&self) -> Result<(), Error> {
+        self.execute("PAUSE SELF").await.map(|_| ())
+    }
+
+    pub async fn pause_all(&self) -> Result<(), Error> {
+        self.execute("PAUSE all").await.map(|_| ())
+    }
+
+    pub async fn pause_uid(&self, target_uid: i32) -> Result<(), Error> {
+        self.execute(&format!("PAUSE {}", target_uid))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn resume(&self) -> Result<(), Error> {
+        self.execute("RESUME SELF").await.map(|_| ())
+    }
+
+    pub async fn resume_all(&self) -> Result<(), Error> {
+        self.execute("RESUME all").await.map(|_| ())
+    }
+
+    pub async fn resume_uid(&self, target_uid: i32) -> Result<(), Error> {
+        self.execute(&format!("RESUME {}", target_uid))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn key(&self, priority: Priority, key: &Key) -> Result<(), Error> {
+        self.message(priority, &format!("KEY {}", key)).await
+    }
+
+    pub async fn char<C: Into<String>>(&self, priority: Priority, char: C) -> Result<(), Error> {
+        let char = char.into();
+        let char = if char == " " { "space".into() } else { char };
+        self.message(priority, &format!("CHAR {}", char)).await
+    }
+
+    pub async fn wchar(&self, priority: Priority, wchar: i32) -> Result<(), Error> {
+        let char = std::char::from_u32(wchar as u32)
+            .ok_or_else(|| Error::Protocol(format!("invalid character {}", wchar)))?;
+        self.char(priority, char.to_string()).await
+    }
+
+    /// Fails with `Error::InvalidSoundIcon` if the name isn't a valid
+    /// `SoundIcon`. The server may be on another machine, so the name isn't
+    /// looked up in the local icon folders.
+    pub async fn sound_icon<I: Into<String>>(
+        &self,
+        priority: Priority,
+        icon_name: I,
+    ) -> Result<(), Error> {
+        let icon = SoundIcon::new(icon_name)?;
+        self.message(priority, &format!("SOUND_ICON {}", icon))
+            .await
+    }
+
+    pub async fn set_voice_type(&self, voice_type: VoiceType) -> Result<(), Error> {
+        self.set("SELF", "VOICE_TYPE", voice_type.as_ssip()).await
+    }
+
+    pub async fn set_voice_type_all(&self, voice_type: VoiceType) -> Result<(), Error> {
+        self.set("all", "VOICE_TYPE", voice_type.as_ssip()).await
+    }
+
+    pub async fn set_voice_type_uid(
+        &self,
+        voice_type: VoiceType,
+        target_uid: u32,
+    ) -> Result<(), Error> {
+        self.set(&target_uid.to_string(), "VOICE_TYPE", voice_type.as_ssip())
+            .await
+    }
+
+    pub async fn get_voice_type(&self) -> Result<VoiceType, Error> {
+        let value = self.get("VOICE_TYPE").await?;
+        value
+            .trim()
+            .parse()
+            .map_err(|e: EnumParseError| Error::Protocol(e.to_string()))
+    }
+
+    pub async fn set_synthesis_voice<V: Into<String>>(&self, voice_name: V) -> Result<(), Error> {
+        self.set("SELF", "SYNTHESIS_VOICE", voice_name.into()).await
+    }
+
+    pub async fn set_synthesis_voice_all<V: Into<String>>(
+        &self,
+        voice_name: V,
+    ) -> Result<(), Error> {
+        self.set("all", "SYNTHESIS_VOICE", voice_name.into()).await
+    }
+
+    pub async fn set_synthesis_voice_uid<V: Into<String>>(
+        &self,
+        voice_name: V,
+        target_uid: u32,
+    ) -> Result<(), Error> {
+        self.set(
+            &target_uid.to_string(),
+            "SYNTHESIS_VOICE",
+            voice_name.into(),
+        )
+        .await
+    }
+
+    pub async fn set_data_mode(&self, mode: DataMode) -> Result<(), Error> {
+        let mut current = self.shared.commands.lock().await;
+        self.command(&format!("SET SELF SSML_MODE {}", mode.ssml_mode()))
+            .await?;
+        *current = mode;
+        Ok(())
+    }
+
+    pub async fn set_notification_on(&self, notification: Notification) -> Result<(), Error> {
+        self.set_notification(notification, "on").await
+    }
+
+    pub async fn set_notification_off(&self, notification: Notification) -> Result<(), Error> {
+        self.set_notification(notification, "off").await
+    }
+
+    pub async fn set_notification<T: Into<String>>(
+        &self,
+        notification: Notification,
+        state: T,
+    ) -> Result<(), Error> {
+        let value = format!("{} {}", notification.as_ssip(), state.into());
+        self.set("SELF", "NOTIFICATION", value).await
+    }
+
+    pub async fn set_voice_rate(&self, rate: i32) -> Result<(), Error> {
+        self.set("SELF", "RATE", rate).await
+    }
+
+    pub async fn set_voice_rate_all(&self, rate: i32) -> Result<(), Error> {
+        self.set("all", "RATE", rate).await
+    }
+
+    pub async fn set_voice_rate_uid(&self, rate: i32, target_uid: u32) -> Result<(), Error> {
+        self.set(&target_uid.to_string(), "RATE", rate).await
+    }
+
+    pub async fn get_voice_rate(&self) -> Result<i32, Error> {
+        self.get_number("RATE").await
+    }
+
+    pub async fn set_voice_pitch(&self, pitch: i32) -> Result<(), Error> {
+        self.set("SELF", "PITCH", pitch).await
+    }
+
+    pub async fn set_voice_pitch_all(&self, pitch: i32) -> Result<(), Error> {
+        self.set("all", "PITCH", pitch).await
+    }
+
+    pub async fn set_voice_pitch_uid(&self, pitch: i32, target_uid: u32) -> Result<(), Error> {
+        self.set(&target_uid.to_string(), "PITCH", pitch).await
+    }
+
+    pub async fn get_voice_pitch(&self) -> Result<i32, Error> {
+        self.get_number("PITCH").await
+    }
+
+    /// Needs speech-dispatcher 0.11 on the server.
+    pub async fn set_voice_pitch_range(&self, pitch_range: i32) -> Result<(), Error> {
+        self.set("SELF", "PITCH_RANGE", pitch_range).await
+    }
+
+    pub async fn set_voice_pitch_range_all(&self, pitch_range: i32) -> Result<(), Error> {
+        self.set("all", "PITCH_RANGE", pitch_range).await
+    }
+
+    pub async fn set_voice_pitch_range_uid(
+        &self,
+        pitch_range: i32,
+        target_uid: u32,
+    ) -> Result<(), Error> {
+        self.set(&target_uid.to_string(), "PITCH_RANGE", pitch_range)
+            .await
+    }
+
+    pub async fn set_volume(&self, volume: i32) -> Result<(), Error> {
+        self.set("SELF", "VOLUME", volume).await
+    }
+
+    pub async fn set_volume_all(&self, volume: i32) -> Result<(), Error> {
+        self.set("all", "VOLUME", volume).await
+    }
+
+    pub async fn set_volume_uid(&self, volume: i32, target_uid: u32) -> Result<(), Error> {
+        self.set(&target_uid.to_string(), "VOLUME", volume).await
+    }
+
+    pub async fn get_volume(&self) -> Result<i32, Error> {
+        self.get_number("VOLUME").await
+    }
+
+    pub async fn set_punctuation(&self, punctuation: Punctuation) -> Result<(), Error> {
+        self.set("SELF", "PUNCTUATION", punctuation.as_ssip()).await
+    }
+
+    pub async fn set_punctuation_all(&self, punctuation: Punctuation) -> Result<(), Error> {
+        self.set("all", "PUNCTUATION", punctuation.as_ssip()).await
+    }
+
+    pub async fn set_punctuation_uid(
+        &self,
+        punctuation: Punctuation,
+        target_uid: u32,
+    ) -> Result<(), Error> {
+        self.set(
+            &target_uid.to_string(),
+            "PUNCTUATION",
+            punctuation.as_ssip(),
+        )
+        .await
+    }
+
+    pub async fn set_capital_letters(&self, capital_letters: CapitalLetters) -> Result<(), Error> {
+        self.set("SELF", "CAP_LET_RECOGN", capital_letters.as_ssip())
+            .await
+    }
+
+    pub async fn set_capital_letters_all(
+        &self,
+        capital_letters: CapitalLetters,
+    ) -> Result<(), Error> {
+        self.set("all", "CAP_LET_RECOGN", capital_letters.as_ssip())
+            .await
+    }
+
+    pub async fn set_capital_letters_uid(
+        &self,
+        capital_letters: CapitalLetters,
+        target_uid: u32,
+    ) -> Result<(), Error> {
+        self.set(
+            &target_uid.to_string(),
+            "CAP_LET_RECOGN",
+            capital_letters.as_ssip(),
+        )
+        .await
+    }
+
+    pub async fn set_spelling(&self, spelling: bool) -> Result<(), Error> {
+        self.set("SELF", "SPELLING", ssip::on_off(spelling)).await
+    }
+
+    pub async fn set_spelling_all(&self, spelling: bool) -> Result<(), Error> {
+        self.set("all", "SPELLING", ssip::on_off(spelling)).await
+    }
+
+    pub async fn set_spelling_uid(&self, spelling: bool, target_uid: u32) -> Result<(), Error> {
+        self.set(&target_uid.to_string(), "SPELLING", ssip::on_off(spelling))
+            .await
+    }
+
+    pub async fn set_language(&self, language: &LanguageTag) -> Result<(), Error> {
+        self.set("SELF", "LANGUAGE", language.to_string()).await
+    }
+
+    pub async fn set_language_all(&self, language: &LanguageTag) -> Result<(), Error> {
+        self.set("all", "LANGUAGE", language.to_string()).await
+    }
+
+    pub async fn set_language_uid(
+        &self,
+        language: &LanguageTag,
+        target_uid: u32,
+    ) -> Result<(), Error> {
+        self.set(&target_uid.to_string(), "LANGUAGE", language.to_string())
+            .await
+    }
+
+    pub async fn get_language(&self) -> Result<LanguageTag, Error> {
+        let value = self.get("LANGUAGE").await?;
+        value
+            .trim()
+            .parse()
+            .map_err(|e: LanguageTagParseError| Error::Protocol(e.to_string()))
+    }
+
+    pub async fn set_output_module<M: Into<String>>(&self, output_module: M) -> Result<(), Error> {
+        self.set("SELF", "OUTPUT_MODULE", output_module.into())
+            .await
+    }
+
+    pub async fn set_output_module_all<M: Into<String>>(
+        &self,
+        output_module: M,
+    ) -> Result<(), Error> {
+        self.set("all", "OUTPUT_MODULE", output_module.into()).await
+    }
+
+    pub async fn set_output_module_uid<M: Into<String>>(
+        &self,
+        output_module: M,
+        target_uid: u32,
+    ) -> Result<(), Error> {
+        self.set(
+            &target_uid.to_string(),
+            "OUTPUT_MODULE",
+            output_module.into(),
+        )
+        .await
+    }
+
+    pub async fn set_pause_context(&self, context: u32) -> Result<(), Error> {
+        self.set("SELF", "PAUSE_CONTEXT", context).await
+    }
+
+    pub async fn set_pause_context_all(&self, context: u32) -> Result<(), Error> {
+        self.set("all", "PAUSE_CONTEXT", context).await
+    }
+
+    pub async fn set_pause_context_uid(&self, context: u32, target_uid: u32) -> Result<(), Error> {
+        self.set(&target_uid.to_string(), "PAUSE_CONTEXT", context)
+            .await
+    }
+
+    /// The voices of the current output module.
+    pub async fn list_synthesis_voices(&self) -> Result<Vec<Voice>, Error> {
+        let reply = self.execute("LIST SYNTHESIS_VOICES").await?;
+        Ok(voice::parse_voices(&reply))
+    }
+
+    pub async fn list_output_modules(&self) -> Result<Vec<String>, Error> {
+        Ok(self.execute("LIST OUTPUT_MODULES").await?.lines)
+    }
+
+    /// The voice types the current output module offers.
+    pub async fn list_voices(&self) -> Result<Vec<VoiceType>, Error> {
+        let reply = self.execute("LIST VOICES").await?;
+        reply
+            .lines
+            .iter()
+            .map(|line| {
+                line.trim()
+                    .parse()
+                    .map_err(|e: EnumParseError| Error::Protocol(e.to_string()))
+            })
+            .collect()
+    }
+
+    /// Probes the server, as `Connection::capabilities` does.
+    pub async fn capabilities(&self) -> Result<Capabilities, Error> {
+        let output_modules = self.execute("LIST OUTPUT_MODULES").await?;
+        let output_module = self.execute("GET OUTPUT_MODULE").await;
+        let history = self.execute("HISTORY GET CLIENT_LIST").await;
+        Ok(Capabilities::from_replies(
+            output_modules,
+            output_module,
+            history,
+        ))
+    }
+
+    pub async fn list_clients(&self) -> Result<Vec<ClientInfo>, Error> {
+        history::parse_clients(&self.execute("HISTORY GET CLIENT_LIST").await?)
+    }
+
+    pub async fn history_messages(
+        &self,
+        client: ClientSpec,
+        start: u32,
+        count: u32,
+    ) -> Result<Vec<HistoryMessage>, Error> {
+        let command = format!("HISTORY GET CLIENT_MESSAGES {} {} {}", client, start, count);
+        history::parse_messages(&self.execute(&command).await?)
+    }
+
+    pub async fn history_last(&self) -> Result<u64, Error> {
+        history::parse_first_number("message id", &self.execute("HISTORY GET LAST").await?)
+    }
+
+    pub async fn history_message_text(&self, msg_id: u64) -> Result<String, Error> {
+        let reply = self
+            .execute(&format!("HISTORY GET MESSAGE {}", msg_id))
+            .await?;
+        Ok(reply.lines.join("\n"))
+    }
+
+    pub async fn history_cursor(&self) -> Result<u32, Error> {
+        history::parse_first_number(
+            "cursor position",
+            &self.execute("HISTORY CURSOR GET").await?,
+        )
+    }
+
+    pub async fn set_history_cursor(
+        &self,
+        client: ClientSpec,
+        position: CursorPosition,
+    ) -> Result<(), Error> {
+        self.execute(&format!("HISTORY CURSOR SET {} {}", client, position))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn history_cursor_forward(&self) -> Result<(), Error> {
+        self.execute("HISTORY CURSOR FORWARD").await.map(|_| ())
+    }
+
+    pub async fn history_cursor_backward(&self) -> Result<(), Error> {
+        self.execute("HISTORY CURSOR BACKWARD").await.map(|_| ())
+    }
+
+    pub async fn history_say(&self, msg_id: u64) -> Result<(), Error> {
+        self.execute(&format!("HISTORY SAY {}", msg_id))
+            .await
+            .map(|_| ())
+    }
+
+    /// Writes raw SSIP that the server answers with exactly one reply, such
+    /// as a single command. With `wait_for_reply` the reply is returned as
+    /// received, error replies included; without it the reply is discarded
+    /// when it arrives.
+    pub async fn send_data<D: Into<String>>(
+        &self,
+        data: D,
+        wait_for_reply: bool,
+    ) -> Result<Option<String>, Error> {
+        let data = data.into();
+        let _lock = self.shared.commands.lock().await;
+        let mut pending = Pending::new(&self.shared);
+        pending.queue(data.as_bytes());
+        if wait_for_reply {
+            Ok(Some(pending.reply().await?.to_raw()))
+        } else {
+            poll_fn(|cx| self.shared.io().poll_flush(cx)).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Notifications received by an [`AsyncConnection`].
+pub struct Events<S> {
+    shared: Arc<Shared<S>>,
+    id: u64,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Stream for Events<S> {
+    type Item = Event;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Event>> {
+        self.shared.io().poll_event(self.id, cx)
+    }
+}
+
+impl<S> Drop for Events<S> {
+    fn drop(&mut self) {
+        let mut io = self.shared.io();
+        io.listeners.remove(&self.id);
+        io.wake_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use futures::{
+        executor::{block_on, LocalPool},
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        task::LocalSpawnExt,
+        FutureExt, StreamExt,
+    };
+
+    use super::*;
+
+    /// One direction of an in-memory pipe.
+    #[derive(Default)]
+    struct Buffer {
+        data: VecDeque<u8>,
+        closed: bool,
+        reader: Option<Waker>,
+    }
+
+    /// One end of an in-memory duplex pipe.
+    struct End {
+        read: Arc<Mutex<Buffer>>,
+        write: Arc<Mutex<Buffer>>,
+    }
+
+    fn pipe() -> (End, End) {
+        let a = Arc::new(Mutex::new(Buffer::default()));
+        let b = Arc::new(Mutex::new(Buffer::default()));
+        (
+            End {
+                read: a.clone(),
+                write: b.clone(),
+            },
+            End { read: b, write: a },
+        )
+    }
+
+    impl AsyncRead for End {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context,
+            out: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut buffer = self.read.lock().unwrap();
+            if buffer.data.is_empty() && !buffer.closed {
+                buffer.reader = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let n = out.len().min(buffer.data.len());
+            for (byte, out) in buffer.data.drain(..n).zip(out) {
+                *out = byte;
+            }
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    impl AsyncWrite for End {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context,
+            data: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut buffer = self.write.lock().unwrap();
+            buffer.data.extend(data);
+            wake(&mut buffer.reader);
+            Poll::Ready(Ok(data.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            let mut buffer = self.write.lock().unwrap();
+            buffer.closed = true;
+            wake(&mut buffer.reader);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl Drop for End {
+        fn drop(&mut self) {
+            let mut buffer = self.write.lock().unwrap();
+            buffer.closed = true;
+            wake(&mut buffer.reader);
+        }
+    }
+
+    /// The server side of the pipe, driven line by line by the test.
+    struct Server(BufReader<End>);
+
+    impl Server {
+        async fn expect(&mut self, command: &str) {
+            let mut line = String::new();
+            self.0.read_line(&mut line).await.unwrap();
+            assert_eq!(line, format!("{}\r\n", command));
+        }
+
+        async fn send(&mut self, data: &str) {
+            self.0.get_mut().write_all(data.as_bytes()).await.unwrap();
+        }
+
+        async fn reply(&mut self, command: &str, reply: &str) {
+            self.expect(command).await;
+            self.send(reply).await;
+        }
+    }
+
+    fn connect() -> (AsyncConnection<End>, Server) {
+        let (client, server) = pipe();
+        let mut server = Server(BufReader::new(server));
+        let (connection, ()) = block_on(futures::future::join(
+            AsyncConnection::open(client, "test", "main", "user").map(Result::unwrap),
+            async {
+                server
+                    .reply(
+                        "SET SELF CLIENT_NAME \"user:test:main\"",
+                        "208 OK CLIENT NAME SET\r\n",
+                    )
+                    .await;
+                server
+                    .reply(
+                        "HISTORY GET CLIENT_ID",
+                        "240-7\r\n240 OK CLIENT ID SENT\r\n",
+                    )
+                    .await;
+                server
+                    .reply(
+                        "SET SELF NOTIFICATION all on",
+                        "218 OK NOTIFICATION SET\r\n",
+                    )
+                    .await;
+            },
+        ));
+        (connection, server)
+    }
+
+    #[test]
+    fn open_reads_client_id() {
+        let (connection, _server) = connect();
+        assert_eq!(connection.client_id(), ClientId(7));
+    }
+
+    #[test]
+    fn say_sends_escaped_text_and_skips_events() {
+        let (connection, mut server) = connect();
+        let mut events = connection.events();
+        let (msg_id, ()) = block_on(futures::future::join(
+            connection.say(Priority::Text, ".hi\nthere"),
+            async {
+                server
+                    .reply("SET SELF PRIORITY text", "202 OK PRIORITY SET\r\n")
+                    .await;
+                server.reply("SPEAK", "230 OK RECEIVING DATA\r\n").await;
+                server.expect("..hi").await;
+                server.expect("there").await;
+                server.expect(".").await;
+                server.send("701-4\r\n701-7\r\n701 BEGIN\r\n").await;
+                server.send("225-5\r\n225 OK MESSAGE QUEUED\r\n").await;
+            },
+        ));
+        assert_eq!(msg_id.unwrap(), 5);
+        assert_eq!(
+            block_on(events.next()),
+            Some(Event::Begin {
+                msg_id: 4,
+                client_id: 7
+            })
+        );
+    }
+
+    #[test]
+    fn dropped_command_does_not_steal_the_next_reply() {
+        let (connection, mut server) = connect();
+        assert!(connection.get_voice_rate().now_or_never().is_none());
+        let (volume, ()) = block_on(futures::future::join(connection.get_volume(), async {
+            server
+                .reply("GET RATE", "251-10\r\n251 OK GET RETURNED\r\n")
+                .await;
+            server
+                .reply("GET VOLUME", "251-80\r\n251 OK GET RETURNED\r\n")
+                .await;
+        }));
+        assert_eq!(volume.unwrap(), 80);
+    }
+
+    #[test]
+    fn dropped_say_ends_the_message() {
+        let (connection, mut server) = connect();
+        let mut say = Box::pin(connection.say(Priority::Text, "hello"));
+        assert!((&mut say).now_or_never().is_none());
+        block_on(server.reply("SET SELF PRIORITY text", "202 OK PRIORITY SET\r\n"));
+        assert!((&mut say).now_or_never().is_none());
+        drop(say);
+        let (rate, ()) = block_on(futures::future::join(connection.get_voice_rate(), async {
+            server.reply("SPEAK", "230 OK RECEIVING DATA\r\n").await;
+            server
+                .reply(".", "225-5\r\n225 OK MESSAGE QUEUED\r\n")
+                .await;
+            server
+                .reply("GET RATE", "251-10\r\n251 OK GET RETURNED\r\n")
+                .await;
+        }));
+        assert_eq!(rate.unwrap(), 10);
+    }
+
+    #[test]
+    fn send_data_without_reply_discards_it() {
+        let (connection, mut server) = connect();
+        let (sent, ()) = block_on(futures::future::join(
+            connection.send_data("STOP SELF\r\n", false),
+            server.expect("STOP SELF"),
+        ));
+        assert_eq!(sent.unwrap(), None);
+        let (rate, ()) = block_on(futures::future::join(connection.get_voice_rate(), async {
+            server.send("210 OK STOPPED\r\n").await;
+            server
+                .reply("GET RATE", "251-10\r\n251 OK GET RETURNED\r\n")
+                .await;
+        }));
+        assert_eq!(rate.unwrap(), 10);
+    }
+
+    #[test]
+    fn lists_voices_and_modules() {
+        let (connection, mut server) = connect();
+        let (voices, ()) = block_on(futures::future::join(
+            connection.list_synthesis_voices(),
+            server.reply(
+                "LIST SYNTHESIS_VOICES",
+                "249-Anna\tde-DE\tnone\r\n249-Klaus\tde-AT\tscottish\r\n\
+                 249-Beep\t\t\r\n249 OK VOICE LIST SENT\r\n",
+            ),
+        ));
+        let voices = voices.unwrap();
+        assert_eq!(voices.len(), 3);
+        assert_eq!(voices[0].name, "Anna");
+        assert_eq!(voices[0].language, Some("de-DE".parse().unwrap()));
+        assert_eq!(voices[0].variant, None);
+        assert_eq!(voices[1].variant.as_deref(), Some("scottish"));
+        assert_eq!(voices[2].language, None);
+
+        let (modules, ()) = block_on(futures::future::join(
+            connection.list_output_modules(),
+            server.reply(
+                "LIST OUTPUT_MODULES",
+                "250-espeak-ng\r\n250-dummy\r\n250 OK MODULE LIST SENT\r\n",
+            ),
+        ));
+        assert_eq!(modules.unwrap(), ["espeak-ng", "dummy"]);
+
+        let (types, ()) = block_on(futures::future::join(
+            connection.list_voices(),
+            server.reply(
+                "LIST VOICES",
+                "249-MALE1\r\n249-CHILD_FEMALE\r\n249 OK VOICE LIST SENT\r\n",
+            ),
+        ));
+        assert_eq!(types.unwrap(), [VoiceType::Male1, VoiceType::ChildFemale]);
+    }
+
+    #[test]
+    fn capabilities_tolerate_failed_probes() {
+        let (connection, mut server) = connect();
+        let (capabilities, ()) =
+            block_on(futures::future::join(connection.capabilities(), async {
+                server
+                    .reply(
+                        "LIST OUTPUT_MODULES",
+                        "250-espeak-ng\r\n250 OK MODULE LIST SENT\r\n",
+                    )
+                    .await;
+                server
+                    .reply(
+                        "GET OUTPUT_MODULE",
+                        "251-espeak-ng\r\n251 OK GET RETURNED\r\n",
+                    )
+                    .await;
+                server
+                    .reply("HISTORY GET CLIENT_LIST", "409 ERR RESTRICTED\r\n")
+                    .await;
+            }));
+        let capabilities = capabilities.unwrap();
+        assert_eq!(capabilities.output_modules, ["espeak-ng"]);
+        assert_eq!(capabilities.output_module.as_deref(), Some("espeak-ng"));
+        assert!(!capabilities.history);
+    }
+
+    #[test]
+    fn set_identity_renames_the_client() {
+        let (connection, mut server) = connect();
+        assert_eq!(connection.identity().to_string(), "user:test:main");
+        let renamed = ClientIdentity::new("user", "reader", "main").unwrap();
+        let (set, ()) = block_on(futures::future::join(
+            connection.set_identity(&renamed),
+            server.reply(
+                "SET SELF CLIENT_NAME \"user:reader:main\"",
+                "208 OK CLIENT NAME SET\r\n",
+            ),
+        ));
+        set.unwrap();
+        assert_eq!(connection.clone().identity(), renamed);
+    }
+
+    #[test]
+    fn every_events_stream_is_woken() {
+        let (connection, mut server) = connect();
+        let mut pool = LocalPool::new();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        for _ in 0..2 {
+            let mut events = connection.events();
+            let received = received.clone();
+            pool.spawner()
+                .spawn_local(async move {
+                    let event = events.next().await;
+                    received.borrow_mut().push(event);
+                })
+                .unwrap();
+        }
+        pool.run_until_stalled();
+        block_on(server.send("702-4\r\n702-7\r\n702 END\r\n"));
+        pool.run_until_stalled();
+        let end = Some(Event::End {
+            msg_id: 4,
+            client_id: 7,
+        });
+        assert_eq!(*received.borrow(), vec![end.clone(), end]);
+    }
+
+    #[test]
+    fn closed_stream_ends_events_and_fails_commands() {
+        let (connection, server) = connect();
+        let mut events = connection.events();
+        drop(server);
+        assert_eq!(block_on(events.next()), None);
+        assert!(block_on(connection.get_voice_rate()).is_err());
+    }
+}
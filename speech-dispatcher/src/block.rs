@@ -0,0 +1,128 @@
+//! `BLOCK BEGIN`/`BLOCK END` grouping.
+
+use std::ops::Deref;
+
+use crate::op_lock::OpGuard;
+use crate::sound_icon;
+use crate::ssip::Reply;
+use crate::{Connection, Error, Key, Priority};
+
+/// Messages sent through a `Block` are queued and interrupted as one unit.
+/// The block ends when the guard is dropped.
+///
+/// The priority is fixed for the whole block, so `say`, `char`, `key` and
+/// `sound_icon` don't take one. Settings can be changed through the
+/// connection the guard derefs to.
+///
+/// Other threads using the connection wait until the block ends, so their
+/// messages don't end up inside it.
+#[derive(Debug)]
+pub struct Block<'a> {
+    connection: &'a Connection,
+    _op: OpGuard<'a>,
+}
+
+impl Connection {
+    /// Opens a block with the given priority. Fails with
+    /// `Error::NestedBlock` if one is already open on this connection.
+    pub fn block(&self, priority: Priority) -> Result<Block<'_>, Error> {
+        let op = self.serialize();
+        {
+            let mut state = self.state();
+            if state.in_block {
+                return Err(Error::NestedBlock);
+            }
+            state.in_block = true;
+        }
+        let begin = self
+            .command(&format!("SET SELF PRIORITY {}", priority.as_ssip()))
+            .and_then(|_| self.command("BLOCK BEGIN"));
+        if let Err(e) = begin {
+            self.state().in_block = false;
+            return Err(e);
+        }
+        Ok(Block {
+            connection: self,
+            _op: op,
+        })
+    }
+}
+
+impl Block<'_> {
+    fn message(&self, command: &str) -> Result<Reply, Error> {
+        self.connection.command(command)
+    }
+
+    pub fn say<S: Into<String>>(&self, text: S) -> Result<u64, Error> {
+        self.connection.speak(&text.into())
+    }
+
+    pub fn char<S: Into<String>>(&self, char: S) -> Result<(), Error> {
+        let char = char.into();
+        let char = if char == " " { "space".into() } else { char };
+        self.message(&format!("CHAR {}", char)).map(|_| ())
+    }
+
+    pub fn key(&self, key: &Key) -> Result<(), Error> {
+        self.message(&format!("KEY {}", key)).map(|_| ())
+    }
+
+    /// Checks the name as `Connection::sound_icon` does.
+    pub fn sound_icon<S: Into<String>>(&self, icon_name: S) -> Result<(), Error> {
+        let icon = sound_icon::resolve_discovered(icon_name.into())?;
+        self.message(&format!("SOUND_ICON {}", icon)).map(|_| ())
+    }
+}
+
+impl Deref for Block<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection
+    }
+}
+
+impl Drop for Block<'_> {
+    fn drop(&mut self) {
+        let _ = self.connection.command("BLOCK END");
+        self.connection.state().in_block = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::tests::dead_connection;
+    use crate::{Error, Priority};
+
+    #[test]
+    fn failed_begin_leaves_no_block_open() {
+        let connection = dead_connection(u64::MAX - 10);
+        assert!(matches!(
+            connection.block(Priority::Text),
+            Err(Error::Disconnected)
+        ));
+        assert!(!connection.state().in_block);
+    }
+
+    #[test]
+    fn block_keeps_other_threads_out() {
+        let connection = dead_connection(u64::MAX - 11);
+        let block = super::Block {
+            connection: &connection,
+            _op: connection.serialize(),
+        };
+        let other = connection.clone();
+        let entered = thread::spawn(move || other.0.ops.try_lock().is_some())
+            .join()
+            .unwrap();
+        assert!(!entered);
+        drop(block);
+        let other = connection.clone();
+        let entered = thread::spawn(move || other.0.ops.try_lock().is_some())
+            .join()
+            .unwrap();
+        assert!(entered);
+    }
+}
@@ -0,0 +1,307 @@
+//! What the linked libspeechd and the running server can do.
+
+use std::fmt;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use speech_dispatcher_sys::{
+    LIBSPEECHD_MAJOR_VERSION, LIBSPEECHD_MICRO_VERSION, LIBSPEECHD_MINOR_VERSION,
+};
+
+use crate::ssip::Reply;
+use crate::{Connection, Error, Event, Priority};
+
+/// A libspeechd release.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub micro: u32,
+}
+
+impl Version {
+    /// The version of the headers the bindings were generated from.
+    pub const HEADERS: Version = Version {
+        major: LIBSPEECHD_MAJOR_VERSION,
+        minor: LIBSPEECHD_MINOR_VERSION,
+        micro: LIBSPEECHD_MICRO_VERSION,
+    };
+
+    /// The libspeechd in use. libspeechd can't report its own version, so
+    /// a linked library has the one pkg-config or `SPEECHD_VERSION` gave at
+    /// build time, falling back to `HEADERS`. With the `dynamic` feature it
+    /// is the oldest release that has every function the loaded library
+    /// exports, and 0.8.0 if none could be loaded.
+    pub fn library() -> Version {
+        #[cfg(feature = "dynamic")]
+        {
+            if speech_dispatcher_sys::has_symbol("spd_get_default_address") {
+                Version::new(0, 11, 0)
+            } else {
+                Version::new(0, 8, 0)
+            }
+        }
+        #[cfg(not(feature = "dynamic"))]
+        {
+            speech_dispatcher_sys::LINKED_VERSION
+                .and_then(Version::parse)
+                .unwrap_or(Version::HEADERS)
+        }
+    }
+
+    /// Parses `major.minor` or `major.minor.micro`.
+    #[cfg_attr(feature = "dynamic", allow(dead_code))]
+    fn parse(version: &str) -> Option<Version> {
+        let mut parts = version.trim().split('.').map(str::parse);
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), micro, None) => Some(Version::new(
+                major,
+                minor,
+                match micro {
+                    Some(Ok(micro)) => micro,
+                    Some(Err(_)) => return None,
+                    None => 0,
+                },
+            )),
+            _ => None,
+        }
+    }
+
+    const fn new(major: u32, minor: u32, micro: u32) -> Self {
+        Self {
+            major,
+            minor,
+            micro,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.micro)
+    }
+}
+
+/// APIs that only some libspeechd releases have, or that none has yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// `Punctuation::Most`.
+    PunctuationMost,
+    /// `spd_set_voice_pitch_range`.
+    PitchRange,
+    /// `spd_list_synthesis_voices2`, which filters by language and variant.
+    FilteredVoiceList,
+    /// `spd_get_default_address`.
+    DefaultAddress,
+    /// Connecting `Connection` to an IPv6 address. libspeechd only connects
+    /// over IPv4, so no release has this; `AsyncConnection` can be used
+    /// over IPv6 instead.
+    Ipv6,
+}
+
+impl Feature {
+    /// Whether the crate was built with the cargo feature that wraps this,
+    /// `0_10` or `0_11`.
+    pub fn enabled(self) -> bool {
+        match self {
+            Feature::PunctuationMost => cfg!(feature = "0_10"),
+            Feature::PitchRange | Feature::FilteredVoiceList | Feature::DefaultAddress => {
+                cfg!(feature = "0_11")
+            }
+            Feature::Ipv6 => false,
+        }
+    }
+
+    /// The libspeechd function behind this feature, looked up in the loaded
+    /// library with the `dynamic` feature.
+    #[cfg(feature = "dynamic")]
+    fn symbol(self) -> Option<&'static str> {
+        match self {
+            Feature::PunctuationMost | Feature::Ipv6 => None,
+            Feature::PitchRange => Some("spd_set_voice_pitch_range"),
+            Feature::FilteredVoiceList => Some("spd_list_synthesis_voices2"),
+            Feature::DefaultAddress => Some("spd_get_default_address"),
+        }
+    }
+
+    /// Whether this can be used: the crate was built with it and, with the
+    /// `dynamic` feature, the libspeechd loaded at runtime has it too.
+    pub fn available(self) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+        #[cfg(feature = "dynamic")]
+        {
+            if let Some(symbol) = self.symbol() {
+                return speech_dispatcher_sys::has_symbol(symbol);
+            }
+        }
+        true
+    }
+
+    /// Fails with `Error::Unsupported` unless the feature is available.
+    #[cfg(feature = "0_11")]
+    pub(crate) fn require(self) -> Result<(), Error> {
+        if self.available() {
+            Ok(())
+        } else {
+            Err(Error::Unsupported(self))
+        }
+    }
+
+    /// The first libspeechd release with this feature, or `None` if there
+    /// is none yet.
+    pub fn since(self) -> Option<Version> {
+        match self {
+            Feature::PunctuationMost => Some(Version::new(0, 10, 0)),
+            Feature::PitchRange | Feature::FilteredVoiceList | Feature::DefaultAddress => {
+                Some(Version::new(0, 11, 0))
+            }
+            Feature::Ipv6 => None,
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Feature::PunctuationMost => "punctuation level \"most\"",
+            Feature::PitchRange => "pitch range",
+            Feature::FilteredVoiceList => "filtered voice listing",
+            Feature::DefaultAddress => "default address lookup",
+            Feature::Ipv6 => "IPv6 connections",
+        };
+        match self.since() {
+            Some(since) => write!(f, "{} (libspeechd {})", name, since),
+            None => write!(f, "{} (not in libspeechd)", name),
+        }
+    }
+}
+
+/// A report of what can be used on a connection. Whether the output module
+/// understands SSML can't be read back and takes speaking to find out; see
+/// `Connection::probe_ssml`.
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    /// The libspeechd version in use, as `Version::library` finds it.
+    pub library_version: Version,
+    /// The output modules the server offers.
+    pub output_modules: Vec<String>,
+    /// The module this client currently speaks with, if the server can
+    /// tell.
+    pub output_module: Option<String>,
+    /// Whether the server answers `HISTORY` queries.
+    pub history: bool,
+}
+
+impl Capabilities {
+    pub fn supports(&self, feature: Feature) -> bool {
+        feature.available()
+            && matches!(feature.since(), Some(since) if self.library_version >= since)
+    }
+
+    /// Fails with `Error::Unsupported` unless `feature` can be used.
+    pub fn require(&self, feature: Feature) -> Result<(), Error> {
+        if self.supports(feature) {
+            Ok(())
+        } else {
+            Err(Error::Unsupported(feature))
+        }
+    }
+}
+
+impl Capabilities {
+    /// Builds the report from the replies to `LIST OUTPUT_MODULES`, `GET
+    /// OUTPUT_MODULE` and `HISTORY GET CLIENT_LIST`, the last two of which
+    /// may fail.
+    pub(crate) fn from_replies(
+        output_modules: Reply,
+        output_module: Result<Reply, Error>,
+        history: Result<Reply, Error>,
+    ) -> Self {
+        Capabilities {
+            library_version: Version::library(),
+            output_modules: output_modules.lines,
+            output_module: output_module
+                .ok()
+                .and_then(|reply| reply.lines.into_iter().next()),
+            history: history.is_ok(),
+        }
+    }
+}
+
+impl Connection {
+    /// Probes the server. Only read-only commands are sent.
+    pub fn capabilities(&self) -> Result<Capabilities, Error> {
+        Ok(Capabilities::from_replies(
+            self.command("LIST OUTPUT_MODULES")?,
+            self.command("GET OUTPUT_MODULE"),
+            self.command("HISTORY GET CLIENT_LIST"),
+        ))
+    }
+
+    /// Whether the current output module understands SSML. An SSML message
+    /// holding only an index mark and a pause too short to hear is spoken,
+    /// and the module handles SSML if the mark is reported within `timeout`;
+    /// modules that don't strip the markup. The message is queued with
+    /// `Priority::Important`, so the answer may wait for other such
+    /// messages.
+    ///
+    /// Fails with `Error::EventsUnavailable` unless the connection was
+    /// opened with `Mode::Threaded`.
+    pub fn probe_ssml(&self, timeout: Duration) -> Result<bool, Error> {
+        const MARK: &str = "probe_ssml";
+        let deadline = Instant::now() + timeout;
+        let (sender, events) = mpsc::channel();
+        // Dropped on the first event after `events` is.
+        self.observe(Box::new(move |event| sender.send(event.clone()).is_ok()))?;
+        let msg_id = self.say_ssml(
+            Priority::Important,
+            format!(
+                "<speak><mark name=\"{}\"/><break time=\"1ms\"/></speak>",
+                MARK
+            ),
+        )?;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match events.recv_timeout(left) {
+                Ok(Event::IndexMark {
+                    msg_id: id, mark, ..
+                }) if id == msg_id && mark == MARK => return Ok(true),
+                Ok(Event::End { msg_id: id, .. }) | Ok(Event::Cancel { msg_id: id, .. })
+                    if id == msg_id =>
+                {
+                    return Ok(false)
+                }
+                Ok(_) => {}
+                Err(_) => return Ok(false),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(Version::parse("0.11"), Some(Version::new(0, 11, 0)));
+        assert_eq!(Version::parse("0.10.2\n"), Some(Version::new(0, 10, 2)));
+        assert_eq!(Version::parse("0"), None);
+        assert_eq!(Version::parse("0.11.x"), None);
+        assert_eq!(Version::parse("0.11.4.1"), None);
+    }
+
+    #[test]
+    fn probe_ssml_needs_events() {
+        let connection = crate::tests::dead_connection(u64::MAX - 100);
+        connection.state().params.mode = Mode::Single;
+        assert!(matches!(
+            connection.probe_ssml(Duration::from_millis(10)),
+            Err(Error::EventsUnavailable)
+        ));
+        assert!(!connection.state().lost);
+    }
+}
@@ -0,0 +1,522 @@
+//! Reading a document aloud with a cursor that can move by sentence,
+//! paragraph and word.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+
+use crate::long_message::{paragraphs, sentences};
+use crate::{Connection, Error, Event, Priority};
+
+/// How many sentences are queued on the server ahead of the one being
+/// spoken.
+const WINDOW: usize = 4;
+
+/// The text and where its paragraphs, sentences and words are.
+#[derive(Debug)]
+struct Document {
+    text: String,
+    paragraphs: Vec<Range<usize>>,
+    sentences: Vec<Range<usize>>,
+    words: Vec<Range<usize>>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let range = |part: &str| {
+            let start = part.as_ptr() as usize - text.as_ptr() as usize;
+            start..start + part.len()
+        };
+        let mut document = Document {
+            paragraphs: Vec::new(),
+            sentences: Vec::new(),
+            words: Vec::new(),
+            text: String::new(),
+        };
+        for paragraph in paragraphs(&text) {
+            document.paragraphs.push(range(paragraph));
+            for sentence in sentences(paragraph) {
+                document.sentences.push(range(sentence));
+                document
+                    .words
+                    .extend(sentence.split_whitespace().map(range));
+            }
+        }
+        document.text = text;
+        document
+    }
+
+    /// The sentence at `index` from the word at `from` on, as SSML with a
+    /// mark before each word named after its offset.
+    fn ssml(&self, index: usize, from: usize) -> String {
+        let sentence = &self.sentences[index];
+        let mut doc = String::from("<speak>");
+        for word in self
+            .words
+            .iter()
+            .filter(|w| w.start >= from.max(sentence.start) && w.end <= sentence.end)
+        {
+            doc.push_str(&format!("<mark name=\"{}\"/>", word.start));
+            for c in self.text[word.clone()].chars() {
+                match c {
+                    '&' => doc.push_str("&amp;"),
+                    '<' => doc.push_str("&lt;"),
+                    '>' => doc.push_str("&gt;"),
+                    c => doc.push(c),
+                }
+            }
+            doc.push(' ');
+        }
+        doc.push_str("</speak>");
+        doc
+    }
+
+    /// Where reading goes on after the sentence at `index`.
+    fn after(&self, index: usize) -> usize {
+        self.sentences
+            .get(index + 1)
+            .map_or(self.text.len(), |s| s.start)
+    }
+}
+
+/// The index of the unit `position` is in, if it isn't before the first.
+fn current(units: &[Range<usize>], position: usize) -> Option<usize> {
+    units.iter().rposition(|u| u.start <= position)
+}
+
+#[derive(Debug, Default)]
+struct ReaderState {
+    position: usize,
+    playing: bool,
+    /// Bumped whenever reading starts or stops, so events and the feeding
+    /// thread of an earlier run are ignored.
+    generation: u64,
+    /// The messages of this run still queued or speaking, with the index
+    /// of their sentence.
+    sent: HashMap<u64, usize>,
+    /// Events that arrived while a message was being sent, before its id
+    /// was known.
+    early: Vec<Event>,
+    sending: bool,
+    done_sending: bool,
+    /// The error that stopped the feeding thread, until taken.
+    error: Option<Error>,
+}
+
+impl ReaderState {
+    fn update(&mut self, event: &Event, document: &Document) {
+        let msg_id = event.msg_id();
+        if self.sending && !self.sent.contains_key(&msg_id) {
+            self.early.push(event.clone());
+            return;
+        }
+        let index = match self.sent.get(&msg_id) {
+            Some(&index) => index,
+            None => return,
+        };
+        match event {
+            Event::IndexMark { mark, .. } => {
+                if let Ok(position) = mark.parse() {
+                    self.position = position;
+                }
+            }
+            Event::End { .. } => {
+                self.position = document.after(index);
+                self.sent.remove(&msg_id);
+                if self.done_sending && self.sent.is_empty() {
+                    self.playing = false;
+                }
+            }
+            // Cancelled from elsewhere: stop here, keeping the position.
+            Event::Cancel { .. } => {
+                self.generation += 1;
+                self.playing = false;
+                self.sent.clear();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<ReaderState>,
+    changed: Condvar,
+}
+
+/// Reads a text aloud from a position that follows the speech, moved by
+/// the navigation methods, which carry on reading from the new position
+/// if it was playing.
+///
+/// Each sentence is sent as its own SSML message with an index mark before
+/// every word, so the position is kept to the word where the output module
+/// supports SSML, and to the sentence otherwise. Positions are byte
+/// offsets into the text. The reader follows events, so it needs a
+/// connection opened with `Mode::Threaded`, and stopping cancels every
+/// message of the connection, so it is best given a connection of its own.
+#[derive(Debug)]
+pub struct DocumentReader {
+    connection: Connection,
+    document: Arc<Document>,
+    shared: Arc<Shared>,
+    priority: Priority,
+}
+
+impl DocumentReader {
+    /// Fails with `Error::EventsUnavailable` unless the connection was
+    /// opened with `Mode::Threaded`.
+    pub fn new<S: Into<String>>(connection: &Connection, text: S) -> Result<Self, Error> {
+        let document = Arc::new(Document::new(text.into()));
+        let shared = Arc::new(Shared::default());
+        shared.state.lock().unwrap().position = document.words.first().map_or(0, |w| w.start);
+        let weak: Weak<Shared> = Arc::downgrade(&shared);
+        let events = document.clone();
+        connection.observe(Box::new(move |event| {
+            let shared = match weak.upgrade() {
+                Some(shared) => shared,
+                None => return false,
+            };
+            shared.state.lock().unwrap().update(event, &events);
+            shared.changed.notify_all();
+            true
+        }))?;
+        Ok(Self {
+            connection: connection.clone(),
+            document,
+            shared,
+            priority: Priority::Text,
+        })
+    }
+
+    /// The priority of the messages, `Priority::Text` unless changed.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.document.text
+    }
+
+    pub fn position(&self) -> usize {
+        self.shared.state.lock().unwrap().position
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.shared.state.lock().unwrap().playing
+    }
+
+    /// The error that stopped reading early, such as the server going
+    /// away. Taking it clears it.
+    pub fn last_error(&self) -> Option<Error> {
+        self.shared.state.lock().unwrap().error.take()
+    }
+
+    /// Reads from the position, starting over once the end was reached.
+    pub fn play(&self) -> Result<(), Error> {
+        self.start(|_| None, true).map(|_| ())
+    }
+
+    /// Moves to the position `seek` gives for the current one, if any, and
+    /// reads from there if `play` is set or it moved while playing. The move and
+    /// the start of the new run happen under one lock, so events of the
+    /// old run can't move the position in between. Returns whether it
+    /// moved.
+    fn start(&self, seek: impl FnOnce(usize) -> Option<usize>, play: bool) -> Result<bool, Error> {
+        let document = &self.document;
+        let _op = self.connection.serialize();
+        let mut state = self.shared.state.lock().unwrap();
+        let moved = match seek(state.position) {
+            Some(position) => {
+                state.position = position;
+                true
+            }
+            None => false,
+        };
+        if !(play || moved && state.playing) || document.sentences.is_empty() {
+            return Ok(moved);
+        }
+        if state.position >= document.text.len() {
+            state.position = document.sentences[0].start;
+        }
+        let was_playing = state.playing;
+        state.generation += 1;
+        state.playing = true;
+        state.sent.clear();
+        state.early.clear();
+        state.done_sending = false;
+        state.error = None;
+        let (generation, from) = (state.generation, state.position);
+        drop(state);
+        self.shared.changed.notify_all();
+        if was_playing {
+            self.connection.command("CANCEL SELF")?;
+        }
+
+        let connection = self.connection.clone();
+        let document = document.clone();
+        let shared = self.shared.clone();
+        let priority = self.priority;
+        thread::spawn(move || feed(connection, document, shared, priority, generation, from));
+        Ok(moved)
+    }
+
+    /// Stops reading, keeping the position so `play` carries on from
+    /// there.
+    pub fn stop(&self) -> Result<(), Error> {
+        let _op = self.connection.serialize();
+        let mut state = self.shared.state.lock().unwrap();
+        let was_playing = state.playing;
+        state.generation += 1;
+        state.playing = false;
+        state.sent.clear();
+        state.early.clear();
+        drop(state);
+        self.shared.changed.notify_all();
+        if was_playing {
+            self.connection.command("CANCEL SELF")?;
+        }
+        Ok(())
+    }
+
+    /// Moves to the word at `offset`, or the next one if it falls between
+    /// words.
+    pub fn set_position(&self, offset: usize) -> Result<(), Error> {
+        let position = self
+            .document
+            .words
+            .iter()
+            .find(|w| w.end > offset)
+            .map_or(self.document.text.len(), |w| w.start);
+        self.start(|_| Some(position), false).map(|_| ())
+    }
+
+    /// Moves to the start of the next or previous unit, returning whether
+    /// there was one.
+    fn step(&self, units: &[Range<usize>], forward: bool) -> Result<bool, Error> {
+        self.start(
+            |position| {
+                let target = match (current(units, position), forward) {
+                    (None, true) => units.first(),
+                    (Some(i), true) => units.get(i + 1),
+                    (Some(i), false) if i > 0 => units.get(i - 1),
+                    _ => None,
+                };
+                target.map(|unit| unit.start)
+            },
+            false,
+        )
+    }
+
+    pub fn next_sentence(&self) -> Result<bool, Error> {
+        self.step(&self.document.sentences, true)
+    }
+
+    pub fn previous_sentence(&self) -> Result<bool, Error> {
+        self.step(&self.document.sentences, false)
+    }
+
+    pub fn next_paragraph(&self) -> Result<bool, Error> {
+        self.step(&self.document.paragraphs, true)
+    }
+
+    pub fn previous_paragraph(&self) -> Result<bool, Error> {
+        self.step(&self.document.paragraphs, false)
+    }
+
+    pub fn next_word(&self) -> Result<bool, Error> {
+        self.step(&self.document.words, true)
+    }
+
+    pub fn previous_word(&self) -> Result<bool, Error> {
+        self.step(&self.document.words, false)
+    }
+
+    fn unit(&self, units: &[Range<usize>]) -> &str {
+        current(units, self.position())
+            .filter(|&i| units[i].end > self.position())
+            .map_or("", |i| &self.document.text[units[i].clone()])
+    }
+
+    /// The sentence at the position, or `""` past the end.
+    pub fn current_sentence(&self) -> &str {
+        self.unit(&self.document.sentences)
+    }
+
+    pub fn current_paragraph(&self) -> &str {
+        self.unit(&self.document.paragraphs)
+    }
+
+    pub fn current_word(&self) -> &str {
+        self.unit(&self.document.words)
+    }
+}
+
+impl Drop for DocumentReader {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Sends the sentences from the word at `from` on, keeping `WINDOW` of
+/// them queued, until the run is replaced or the text runs out.
+fn feed(
+    connection: Connection,
+    document: Arc<Document>,
+    shared: Arc<Shared>,
+    priority: Priority,
+    generation: u64,
+    mut from: usize,
+) {
+    let mut index = current(&document.sentences, from).unwrap_or(0);
+    loop {
+        let mut state = shared.state.lock().unwrap();
+        loop {
+            if state.generation != generation {
+                return;
+            }
+            if index >= document.sentences.len() {
+                state.done_sending = true;
+                if state.sent.is_empty() {
+                    state.playing = false;
+                }
+                return;
+            }
+            if state.sent.len() < WINDOW {
+                break;
+            }
+            state = shared.changed.wait(state).unwrap();
+        }
+        drop(state);
+
+        let _op = connection.serialize();
+        let mut state = shared.state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+        state.sending = true;
+        drop(state);
+        let sent = connection.say_ssml(priority, document.ssml(index, from));
+        let mut state = shared.state.lock().unwrap();
+        state.sending = false;
+        let early = std::mem::take(&mut state.early);
+        match sent {
+            Ok(msg_id) if state.generation == generation => {
+                state.sent.insert(msg_id, index);
+            }
+            Ok(_) => return,
+            Err(e) => {
+                state.generation += 1;
+                state.playing = false;
+                state.error = Some(e);
+                return;
+            }
+        }
+        for event in &early {
+            state.update(event, &document);
+        }
+        index += 1;
+        from = document.after(index - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssml::check;
+    use crate::Mode;
+
+    const TEXT: &str = "Hello, <world> & you.\n\nNext one. Last";
+
+    #[test]
+    fn new_finds_paragraphs_sentences_and_words() {
+        let document = Document::new(TEXT.to_string());
+        assert_eq!(document.paragraphs, [0..21, 23..37]);
+        assert_eq!(document.sentences, [0..21, 23..32, 33..37]);
+        let words: Vec<&str> = document
+            .words
+            .iter()
+            .map(|w| &document.text[w.clone()])
+            .collect();
+        assert_eq!(
+            words,
+            ["Hello,", "<world>", "&", "you.", "Next", "one.", "Last"]
+        );
+        assert_eq!(document.after(0), 23);
+        assert_eq!(document.after(2), TEXT.len());
+    }
+
+    #[test]
+    fn new_keeps_multibyte_ranges() {
+        let document = Document::new("Ça va… “Oui.” Bien".to_string());
+        let sentences: Vec<&str> = document
+            .sentences
+            .iter()
+            .map(|s| &document.text[s.clone()])
+            .collect();
+        assert_eq!(sentences, ["Ça va…", "“Oui.”", "Bien"]);
+    }
+
+    #[test]
+    fn ssml_escapes_and_marks_words() {
+        let document = Document::new(TEXT.to_string());
+        let ssml = document.ssml(0, 0);
+        assert_eq!(
+            ssml,
+            "<speak><mark name=\"0\"/>Hello, <mark name=\"7\"/>&lt;world&gt; \
+             <mark name=\"15\"/>&amp; <mark name=\"17\"/>you. </speak>"
+        );
+        assert!(check(&ssml).is_ok());
+        assert_eq!(
+            document.ssml(0, 10),
+            "<speak><mark name=\"15\"/>&amp; <mark name=\"17\"/>you. </speak>"
+        );
+        assert_eq!(
+            document.ssml(2, 0),
+            "<speak><mark name=\"33\"/>Last </speak>"
+        );
+    }
+
+    #[test]
+    fn current_finds_the_unit_at_a_position() {
+        let document = Document::new(TEXT.to_string());
+        assert_eq!(current(&document.sentences, 0), Some(0));
+        assert_eq!(current(&document.sentences, 22), Some(0));
+        assert_eq!(current(&document.sentences, 33), Some(2));
+        assert_eq!(current(&document.words, 12), Some(1));
+        assert_eq!(current(&[5..6, 8..9], 0), None);
+    }
+
+    #[test]
+    fn navigation_moves_the_position() {
+        let connection = crate::tests::dead_connection(u64::MAX - 50);
+        let reader = DocumentReader::new(&connection, TEXT).unwrap();
+        assert_eq!(reader.current_sentence(), "Hello, <world> & you.");
+        assert!(reader.next_sentence().unwrap());
+        assert_eq!(reader.position(), 23);
+        assert_eq!(reader.current_paragraph(), "Next one. Last");
+        assert!(reader.next_word().unwrap());
+        assert_eq!(reader.current_word(), "one.");
+        assert!(reader.previous_paragraph().unwrap());
+        assert_eq!(reader.position(), 0);
+        assert!(!reader.previous_paragraph().unwrap());
+        reader.set_position(12).unwrap();
+        assert_eq!(reader.position(), 7);
+        reader.set_position(14).unwrap();
+        assert_eq!(reader.position(), 15);
+        reader.set_position(TEXT.len()).unwrap();
+        assert_eq!(reader.current_sentence(), "");
+        assert!(!reader.is_playing());
+    }
+
+    #[test]
+    fn new_needs_events() {
+        let connection = crate::tests::dead_connection(u64::MAX - 51);
+        connection.state().params.mode = Mode::Single;
+        assert!(matches!(
+            DocumentReader::new(&connection, TEXT),
+            Err(Error::EventsUnavailable)
+        ));
+    }
+}
@@ -0,0 +1,80 @@
+use std::{error, fmt, io};
+
+use crate::{ClientId, Feature};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading from or writing to the server failed.
+    Io(io::Error),
+    /// The server answered a command with an error reply.
+    Server { code: u16, message: String },
+    /// The server sent something that isn't valid SSIP.
+    Protocol(String),
+    /// The server didn't answer within the connection's timeout.
+    Timeout,
+    /// The connection was closed or the server went away.
+    Disconnected,
+    /// A block was opened while another one was still open.
+    NestedBlock,
+    /// A sound icon name can't be sent or would point outside the icon
+    /// directory.
+    InvalidSoundIcon(String),
+    /// No sound icon of that name, or for that event, is installed.
+    UnknownSoundIcon(String),
+    /// An SSML document isn't well formed.
+    InvalidSsml(String),
+    /// A client name part is empty or contains characters SSIP doesn't
+    /// allow.
+    InvalidIdentity(String),
+    /// The crate was built without a feature or against a libspeechd that
+    /// lacks it.
+    Unsupported(Feature),
+    /// libspeechd couldn't be loaded at runtime.
+    LibraryUnavailable(String),
+    /// A profile couldn't be parsed, serialized or found.
+    InvalidProfile(String),
+    /// Something that follows the server's events was given a connection
+    /// not opened with `Mode::Threaded`, which gets none.
+    EventsUnavailable,
+    /// A client id is too large for libspeechd to target.
+    InvalidClientId(ClientId),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Server { code, message } => write!(f, "server error {}: {}", code, message),
+            Error::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            Error::Timeout => write!(f, "timed out waiting for the server"),
+            Error::Disconnected => write!(f, "not connected to the server"),
+            Error::NestedBlock => write!(f, "a block is already open on this connection"),
+            Error::InvalidSoundIcon(name) => write!(f, "invalid sound icon name {:?}", name),
+            Error::UnknownSoundIcon(name) => write!(f, "unknown sound icon {:?}", name),
+            Error::InvalidSsml(msg) => write!(f, "invalid SSML: {}", msg),
+            Error::InvalidIdentity(name) => write!(f, "invalid client name {:?}", name),
+            Error::Unsupported(feature) => write!(f, "unsupported: {}", feature),
+            Error::LibraryUnavailable(msg) => write!(f, "libspeechd is unavailable: {}", msg),
+            Error::InvalidProfile(msg) => write!(f, "invalid profile: {}", msg),
+            Error::EventsUnavailable => {
+                write!(f, "events need a connection opened in threaded mode")
+            }
+            Error::InvalidClientId(id) => write!(f, "client id {} is out of range", id),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
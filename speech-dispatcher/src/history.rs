@@ -0,0 +1,246 @@
+//! The `HISTORY` command family: the clients the server has seen and the
+//! messages they sent.
+
+use std::fmt;
+
+use crate::ssip::Reply;
+use crate::{ClientId, Connection, Error, Priority};
+
+/// A client as listed by `HISTORY GET CLIENT_LIST`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub id: ClientId,
+    /// The full `user:application:component` name.
+    pub name: String,
+    /// Whether the client is still connected.
+    pub active: bool,
+}
+
+/// A message in the history, as listed by `HISTORY GET CLIENT_MESSAGES`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryMessage {
+    pub id: u64,
+    /// The full `user:application:component` name of the sender.
+    pub client_name: String,
+    /// When the server received the message, as the server formats it.
+    /// `None` if the server doesn't say.
+    pub time: Option<String>,
+    /// `None` if the server doesn't say.
+    pub priority: Option<Priority>,
+}
+
+/// Whose history a command looks at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientSpec {
+    /// The client sending the command.
+    This,
+    All,
+    Id(ClientId),
+}
+
+impl fmt::Display for ClientSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientSpec::This => write!(f, "self"),
+            ClientSpec::All => write!(f, "all"),
+            ClientSpec::Id(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Where `HISTORY CURSOR SET` moves the cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorPosition {
+    First,
+    Last,
+    /// A position counted from the first message.
+    Pos(u32),
+}
+
+impl fmt::Display for CursorPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CursorPosition::First => write!(f, "first"),
+            CursorPosition::Last => write!(f, "last"),
+            CursorPosition::Pos(pos) => write!(f, "pos {}", pos),
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(what: &str, value: &str) -> Result<T, Error> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| Error::Protocol(format!("invalid {} {:?}", what, value)))
+}
+
+pub(crate) fn parse_clients(reply: &Reply) -> Result<Vec<ClientInfo>, Error> {
+    reply
+        .lines
+        .iter()
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let mut field = || {
+                fields
+                    .next()
+                    .ok_or_else(|| Error::Protocol(format!("invalid client {:?}", line)))
+            };
+            Ok(ClientInfo {
+                id: ClientId(parse_number("client id", field()?)?),
+                name: field()?.to_string(),
+                active: field()? == "1",
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn parse_messages(reply: &Reply) -> Result<Vec<HistoryMessage>, Error> {
+    reply.lines.iter().map(|line| parse_message(line)).collect()
+}
+
+/// Parses `ID CLIENT_NAME`, optionally followed by the time and priority.
+/// The priority is the field that names one; the rest is the time, which
+/// may span several fields.
+fn parse_message(line: &str) -> Result<HistoryMessage, Error> {
+    let mut fields = line.split_whitespace();
+    let id = fields.next().unwrap_or_default();
+    let client_name = fields.next().unwrap_or_default().to_string();
+    let mut time = Vec::new();
+    let mut priority = None;
+    for field in fields {
+        match field.parse() {
+            Ok(p) if priority.is_none() => priority = Some(p),
+            _ => time.push(field),
+        }
+    }
+    Ok(HistoryMessage {
+        id: parse_number("message id", id)?,
+        client_name,
+        time: if time.is_empty() {
+            None
+        } else {
+            Some(time.join(" "))
+        },
+        priority,
+    })
+}
+
+pub(crate) fn parse_first_number<T: std::str::FromStr>(
+    what: &str,
+    reply: &Reply,
+) -> Result<T, Error> {
+    let value = reply.value()?;
+    parse_number(what, value.split_whitespace().next().unwrap_or(value))
+}
+
+impl Connection {
+    /// Every client the server has seen since it started.
+    pub fn list_clients(&self) -> Result<Vec<ClientInfo>, Error> {
+        parse_clients(&self.command("HISTORY GET CLIENT_LIST")?)
+    }
+
+    /// Up to `count` messages of `client`, starting at `start`, the oldest
+    /// message being 1, with who sent them, when and at what priority.
+    pub fn history_messages(
+        &self,
+        client: ClientSpec,
+        start: u32,
+        count: u32,
+    ) -> Result<Vec<HistoryMessage>, Error> {
+        parse_messages(&self.command(&format!(
+            "HISTORY GET CLIENT_MESSAGES {} {} {}",
+            client, start, count
+        ))?)
+    }
+
+    /// The id of the most recent message in the history.
+    pub fn history_last(&self) -> Result<u64, Error> {
+        parse_first_number("message id", &self.command("HISTORY GET LAST")?)
+    }
+
+    /// The text of a message in the history. Its sender, time and priority
+    /// come with `history_messages`.
+    pub fn history_message_text(&self, msg_id: u64) -> Result<String, Error> {
+        let reply = self.command(&format!("HISTORY GET MESSAGE {}", msg_id))?;
+        Ok(reply.lines.join("\n"))
+    }
+
+    /// The position of this client's history cursor.
+    pub fn history_cursor(&self) -> Result<u32, Error> {
+        parse_first_number("cursor position", &self.command("HISTORY CURSOR GET")?)
+    }
+
+    pub fn set_history_cursor(
+        &self,
+        client: ClientSpec,
+        position: CursorPosition,
+    ) -> Result<(), Error> {
+        self.command(&format!("HISTORY CURSOR SET {} {}", client, position))
+            .map(|_| ())
+    }
+
+    pub fn history_cursor_forward(&self) -> Result<(), Error> {
+        self.command("HISTORY CURSOR FORWARD").map(|_| ())
+    }
+
+    pub fn history_cursor_backward(&self) -> Result<(), Error> {
+        self.command("HISTORY CURSOR BACKWARD").map(|_| ())
+    }
+
+    /// Speaks a message from the history again.
+    pub fn history_say(&self, msg_id: u64) -> Result<(), Error> {
+        self.command(&format!("HISTORY SAY {}", msg_id)).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(lines: &[&str]) -> Reply {
+        Reply {
+            code: 242,
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+            message: "OK MESSAGES LIST SENT".into(),
+        }
+    }
+
+    #[test]
+    fn parses_message_metadata() {
+        let messages = parse_messages(&reply(&[
+            "12 joe:orca:main 2024-05-01 10:15:02 important",
+            "13 joe:orca:main text",
+            "14 joe:orca:main",
+        ]))
+        .unwrap();
+        assert_eq!(
+            messages[0],
+            HistoryMessage {
+                id: 12,
+                client_name: "joe:orca:main".into(),
+                time: Some("2024-05-01 10:15:02".into()),
+                priority: Some(Priority::Important),
+            }
+        );
+        assert_eq!(messages[1].time, None);
+        assert_eq!(messages[1].priority, Some(Priority::Text));
+        assert_eq!(
+            (messages[2].time.clone(), messages[2].priority),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_message_id() {
+        assert!(parse_messages(&reply(&["x joe:orca:main"])).is_err());
+    }
+
+    #[test]
+    fn parses_clients() {
+        let clients =
+            parse_clients(&reply(&["3 joe:orca:main 1", "4 joe:emacs:speechd 0"])).unwrap();
+        assert_eq!(clients[0].id, ClientId(3));
+        assert_eq!(clients[1].name, "joe:emacs:speechd");
+        assert!(clients[0].active && !clients[1].active);
+    }
+}
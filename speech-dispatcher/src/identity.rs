@@ -0,0 +1,152 @@
+//! The `user:application:component` name a client is known by.
+
+use std::ffi::CString;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Connection, Error};
+
+/// How a client labels itself in the server's logs and history. The server
+/// joins the parts with colons, so none of them may contain one, nor quotes,
+/// whitespace or control characters.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClientIdentity {
+    user: String,
+    application: String,
+    component: String,
+}
+
+impl ClientIdentity {
+    pub fn new<S: Into<String>>(user: S, application: S, component: S) -> Result<Self, Error> {
+        let identity = Self {
+            user: user.into(),
+            application: application.into(),
+            component: component.into(),
+        };
+        for part in [&identity.user, &identity.application, &identity.component].iter() {
+            if part.is_empty()
+                || part
+                    .chars()
+                    .any(|c| c == ':' || c == '"' || c.is_whitespace() || c.is_control())
+            {
+                return Err(Error::InvalidIdentity(identity.to_string()));
+            }
+        }
+        Ok(identity)
+    }
+
+    /// The names a connection was opened with, which aren't checked.
+    pub(crate) fn unchecked(user: String, application: String, component: String) -> Self {
+        Self {
+            user,
+            application,
+            component,
+        }
+    }
+
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn application(&self) -> &str {
+        &self.application
+    }
+
+    pub fn component(&self) -> &str {
+        &self.component
+    }
+}
+
+impl fmt::Display for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.user, self.application, self.component)
+    }
+}
+
+impl FromStr for ClientIdentity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(user), Some(application), Some(component)) => {
+                Self::new(user, application, component)
+            }
+            _ => Err(Error::InvalidIdentity(s.to_string())),
+        }
+    }
+}
+
+impl Connection {
+    /// The names this connection was opened with or last given by
+    /// `set_identity`.
+    pub fn identity(&self) -> ClientIdentity {
+        let state = self.state();
+        let params = &state.params;
+        ClientIdentity::unchecked(
+            params.user_name.to_string_lossy().into_owned(),
+            params.client_name.to_string_lossy().into_owned(),
+            params.connection_name.to_string_lossy().into_owned(),
+        )
+    }
+
+    /// Renames the client. The new name is also used if the connection is
+    /// reopened.
+    pub fn set_identity(&self, identity: &ClientIdentity) -> Result<(), Error> {
+        self.command(&format!("SET SELF CLIENT_NAME \"{}\"", identity))?;
+        let mut state = self.state();
+        state.params.user_name = CString::new(identity.user.as_str()).unwrap();
+        state.params.client_name = CString::new(identity.application.as_str()).unwrap();
+        state.params.connection_name = CString::new(identity.component.as_str()).unwrap();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_displays() {
+        let identity: ClientIdentity = "alice:orca:speech".parse().unwrap();
+        assert_eq!(identity.user(), "alice");
+        assert_eq!(identity.application(), "orca");
+        assert_eq!(identity.component(), "speech");
+        assert_eq!(identity.to_string(), "alice:orca:speech");
+        assert_eq!(
+            ClientIdentity::new("bob", "éditeur", "main")
+                .unwrap()
+                .to_string(),
+            "bob:éditeur:main"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_parts() {
+        for s in &[
+            "",
+            "alice:orca",
+            "alice::speech",
+            "alice:orca:speech:extra",
+            "alice:my app:speech",
+            "alice:\"orca\":speech",
+            "alice:orca:\tspeech",
+            "alice:orca:speech\u{0}",
+        ] {
+            assert!(
+                matches!(s.parse::<ClientIdentity>(), Err(Error::InvalidIdentity(_))),
+                "{:?} parsed",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn identity_follows_the_open_parameters() {
+        let connection = crate::tests::dead_connection(u64::MAX - 60);
+        assert_eq!(connection.identity().to_string(), "user:test:main");
+        let renamed = ClientIdentity::new("user", "reader", "main").unwrap();
+        assert!(connection.set_identity(&renamed).is_err());
+        assert_eq!(connection.identity().to_string(), "user:test:main");
+    }
+}
@@ -0,0 +1,249 @@
+//! Key names for the SSIP `KEY` command.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitflags::bitflags;
+
+bitflags! {
+    /// Modifiers held with a key, written as `control_`, `alt_` and so on
+    /// before its name.
+    pub struct Modifiers: u8 {
+        const CONTROL = 1 << 0;
+        const ALT = 1 << 1;
+        const SHIFT = 1 << 2;
+        const SUPER = 1 << 3;
+        const HYPER = 1 << 4;
+        const META = 1 << 5;
+    }
+}
+
+const MODIFIER_NAMES: [(Modifiers, &str); 6] = [
+    (Modifiers::CONTROL, "control"),
+    (Modifiers::ALT, "alt"),
+    (Modifiers::SHIFT, "shift"),
+    (Modifiers::SUPER, "super"),
+    (Modifiers::HYPER, "hyper"),
+    (Modifiers::META, "meta"),
+];
+
+/// The key names SSIP defines besides single characters.
+const NAMED_KEYS: &[&str] = &[
+    "space",
+    "underscore",
+    "double-quote",
+    "alt",
+    "control",
+    "hyper",
+    "meta",
+    "shift",
+    "super",
+    "backspace",
+    "break",
+    "delete",
+    "down",
+    "end",
+    "enter",
+    "escape",
+    "f1",
+    "f2",
+    "f3",
+    "f4",
+    "f5",
+    "f6",
+    "f7",
+    "f8",
+    "f9",
+    "f10",
+    "f11",
+    "f12",
+    "f13",
+    "f14",
+    "f15",
+    "f16",
+    "f17",
+    "f18",
+    "f19",
+    "f20",
+    "f21",
+    "f22",
+    "f23",
+    "f24",
+    "home",
+    "insert",
+    "kp-*",
+    "kp-+",
+    "kp--",
+    "kp-.",
+    "kp-/",
+    "kp-0",
+    "kp-1",
+    "kp-2",
+    "kp-3",
+    "kp-4",
+    "kp-5",
+    "kp-6",
+    "kp-7",
+    "kp-8",
+    "kp-9",
+    "kp-enter",
+    "left",
+    "menu",
+    "next",
+    "num-lock",
+    "pause",
+    "print",
+    "prior",
+    "return",
+    "right",
+    "scroll-lock",
+    "tab",
+    "up",
+    "window",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyParseError(String);
+
+impl fmt::Display for KeyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid key name {:?}", self.0)
+    }
+}
+
+impl std::error::Error for KeyParseError {}
+
+/// A key, with modifiers, in the form the server can pronounce, such as
+/// `a`, `kp-enter` or `control_alt_delete`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    modifiers: Modifiers,
+    name: String,
+}
+
+impl Key {
+    /// A named key such as `backspace` or `f5`, or a single character.
+    pub fn new(name: &str) -> Result<Self, KeyParseError> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Self::char(c),
+            _ => {
+                let lower = name.to_lowercase();
+                if NAMED_KEYS.contains(&lower.as_str()) {
+                    Ok(Self {
+                        modifiers: Modifiers::empty(),
+                        name: lower,
+                    })
+                } else {
+                    Err(KeyParseError(name.to_string()))
+                }
+            }
+        }
+    }
+
+    /// The key that types `c`. Space, underscore and double quote map to
+    /// their names, since they can't appear in the command as themselves.
+    pub fn char(c: char) -> Result<Self, KeyParseError> {
+        let name = match c {
+            ' ' => "space".to_string(),
+            '_' => "underscore".to_string(),
+            '"' => "double-quote".to_string(),
+            c if c.is_whitespace() || c.is_control() => {
+                return Err(KeyParseError(c.to_string()));
+            }
+            c => c.to_string(),
+        };
+        Ok(Self {
+            modifiers: Modifiers::empty(),
+            name,
+        })
+    }
+
+    pub fn with(mut self, modifiers: Modifiers) -> Self {
+        self.modifiers |= modifiers;
+        self
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// The key's name without modifiers.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl FromStr for Key {
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || KeyParseError(s.to_string());
+        let mut parts: Vec<&str> = s.split('_').collect();
+        let name = parts.pop().ok_or_else(err)?;
+        let mut modifiers = Modifiers::empty();
+        for part in parts {
+            let (modifier, _) = MODIFIER_NAMES
+                .iter()
+                .find(|(_, name)| part.eq_ignore_ascii_case(name))
+                .ok_or_else(err)?;
+            modifiers |= *modifier;
+        }
+        Ok(Key::new(name).map_err(|_| err())?.with(modifiers))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in MODIFIER_NAMES.iter() {
+            if self.modifiers.contains(*modifier) {
+                write!(f, "{}_", name)?;
+            }
+        }
+        f.write_str(&self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys_with_modifiers() {
+        let key: Key = "control_alt_delete".parse().unwrap();
+        assert_eq!(key.name(), "delete");
+        assert_eq!(key.modifiers(), Modifiers::CONTROL | Modifiers::ALT);
+        let key: Key = "SHIFT_F5".parse().unwrap();
+        assert_eq!(key.name(), "f5");
+        assert_eq!(key.modifiers(), Modifiers::SHIFT);
+        assert_eq!("kp-enter".parse::<Key>().unwrap().name(), "kp-enter");
+        assert_eq!("é".parse::<Key>().unwrap().name(), "é");
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_modifiers() {
+        for s in &["", "_", "shift_", "ctrl_a", "f25", "control__a", "ab"] {
+            assert!(s.parse::<Key>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn char_names_keys_ssip_cant_spell() {
+        assert_eq!(Key::char(' ').unwrap().name(), "space");
+        assert_eq!(Key::char('_').unwrap().name(), "underscore");
+        assert_eq!(Key::char('"').unwrap().name(), "double-quote");
+        assert!(Key::char('\t').is_err());
+        assert!(Key::char('\u{7}').is_err());
+    }
+
+    #[test]
+    fn display_puts_modifiers_in_order() {
+        let key: Key = "alt_control_x".parse().unwrap();
+        assert_eq!(key.to_string(), "control_alt_x");
+        let key = Key::char('_')
+            .unwrap()
+            .with(Modifiers::META | Modifiers::SHIFT);
+        assert_eq!(key.to_string(), "shift_meta_underscore");
+        assert_eq!(key.to_string().parse::<Key>().unwrap(), key);
+    }
+}
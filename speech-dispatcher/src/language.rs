@@ -0,0 +1,183 @@
+//! BCP 47 language tags, as the server takes and lists them.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LanguageTagParseError(String);
+
+impl fmt::Display for LanguageTagParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid language tag {:?}", self.0)
+    }
+}
+
+impl std::error::Error for LanguageTagParseError {}
+
+/// A well-formed BCP 47 language tag such as `en-US` or `zh-Hant-TW`.
+///
+/// Parsing also takes POSIX locale names such as `pt_BR.UTF-8`, dropping the
+/// codeset and modifier, and gives subtags their conventional case: the
+/// language lowercase, the script titlecase and the region uppercase.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LanguageTag(String);
+
+impl LanguageTag {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The subtags, in order, such as `zh`, `Hant` and `TW`.
+    pub fn subtags(&self) -> impl Iterator<Item = &str> {
+        self.0.split('-')
+    }
+
+    /// The primary language subtag, such as `zh`.
+    pub fn language(&self) -> &str {
+        self.subtags().next().unwrap()
+    }
+
+    /// The four-letter script subtag, such as `Hant`.
+    pub fn script(&self) -> Option<&str> {
+        self.leading().find(|s| s.len() == 4 && is_alpha(s))
+    }
+
+    /// The region subtag, such as `TW` or `419`.
+    pub fn region(&self) -> Option<&str> {
+        self.leading()
+            .find(|s| (s.len() == 2 && is_alpha(s)) || (s.len() == 3 && is_digit(s)))
+    }
+
+    /// The subtags after the language and before any extension or private
+    /// use part.
+    fn leading(&self) -> impl Iterator<Item = &str> {
+        self.subtags().skip(1).take_while(|s| s.len() > 1)
+    }
+}
+
+fn is_alpha(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+fn is_digit(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_digit())
+}
+
+impl FromStr for LanguageTag {
+    type Err = LanguageTagParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || LanguageTagParseError(s.to_string());
+        let tag = s.split(['.', '@']).next().unwrap_or_default();
+        let mut subtags = tag.split(['-', '_']);
+        let language = subtags.next().unwrap_or_default();
+        let private = language.eq_ignore_ascii_case("x") || language.eq_ignore_ascii_case("i");
+        if (!private && !(2..=8).contains(&language.len())) || !is_alpha(language) {
+            return Err(err());
+        }
+        let mut normalized = language.to_ascii_lowercase();
+        // Everything after a singleton, such as `x`, keeps to lowercase.
+        let mut extension = private;
+        for subtag in subtags {
+            if !(1..=8).contains(&subtag.len())
+                || !subtag.bytes().all(|b| b.is_ascii_alphanumeric())
+            {
+                return Err(err());
+            }
+            normalized.push('-');
+            if subtag.len() == 1 {
+                extension = true;
+            }
+            if !extension && subtag.len() == 2 && is_alpha(subtag) {
+                normalized.push_str(&subtag.to_ascii_uppercase());
+            } else if !extension && subtag.len() == 4 && is_alpha(subtag) {
+                normalized.push_str(&subtag[..1].to_ascii_uppercase());
+                normalized.push_str(&subtag[1..].to_ascii_lowercase());
+            } else {
+                normalized.push_str(&subtag.to_ascii_lowercase());
+            }
+        }
+        Ok(LanguageTag(normalized))
+    }
+}
+
+impl fmt::Display for LanguageTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for LanguageTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for LanguageTag {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for LanguageTag {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(s: &str) -> LanguageTag {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn normalizes_case() {
+        assert_eq!(tag("en-us").as_str(), "en-US");
+        assert_eq!(tag("ZH-hant-tw").as_str(), "zh-Hant-TW");
+        assert_eq!(tag("de-DE-1996").as_str(), "de-DE-1996");
+        assert_eq!(tag("en-x-AB-Priv").as_str(), "en-x-ab-priv");
+    }
+
+    #[test]
+    fn takes_posix_locales() {
+        assert_eq!(tag("pt_BR.UTF-8").as_str(), "pt-BR");
+        assert_eq!(tag("sr_RS@latin").as_str(), "sr-RS");
+        assert!("C.UTF-8".parse::<LanguageTag>().is_err());
+    }
+
+    #[test]
+    fn finds_subtags() {
+        let zh = tag("zh-Hant-TW");
+        assert_eq!(zh.language(), "zh");
+        assert_eq!(zh.script(), Some("Hant"));
+        assert_eq!(zh.region(), Some("TW"));
+        assert_eq!(tag("es-419").region(), Some("419"));
+        let private = tag("en-x-ab");
+        assert_eq!(private.region(), None);
+        assert_eq!(private.subtags().collect::<Vec<_>>(), ["en", "x", "ab"]);
+        assert_eq!(tag("x-klingon").language(), "x");
+        assert_eq!(tag("i-klingon").script(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_tags() {
+        for s in &[
+            "",
+            "e",
+            "123",
+            "toolonglang",
+            "en--US",
+            "en-US!",
+            "en-abcdefghi",
+            "en-",
+        ] {
+            assert!(s.parse::<LanguageTag>().is_err(), "{:?} parsed", s);
+        }
+    }
+}
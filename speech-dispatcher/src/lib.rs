@@ -2,20 +2,88 @@
 
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
-use std::marker::Send;
-use std::sync::Mutex;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use lazy_static::lazy_static;
 use speech_dispatcher_sys::*;
 
-#[derive(Clone, Copy, Debug)]
+mod address;
+#[cfg(feature = "async")]
+mod async_connection;
+mod block;
+mod capabilities;
+mod document_reader;
+mod error;
+mod history;
+mod identity;
+mod key;
+mod language;
+mod long_message;
+mod names;
+mod op_lock;
+mod profile;
+mod reconnect;
+mod scoped;
+mod settings;
+mod sound_icon;
+#[cfg_attr(not(feature = "async"), allow(dead_code))]
+mod ssip;
+mod ssml;
+mod supervisor;
+mod utterance;
+mod voice;
+mod voice_query;
+mod watchdog;
+
+pub use address::{Address, AddressParseError, Stream};
+#[cfg(feature = "async")]
+pub use async_connection::{AsyncConnection, Events};
+pub use block::Block;
+pub use capabilities::{Capabilities, Feature, Version};
+pub use document_reader::DocumentReader;
+pub use error::Error;
+pub use history::{ClientInfo, ClientSpec, CursorPosition, HistoryMessage};
+pub use identity::ClientIdentity;
+pub use key::{Key, KeyParseError, Modifiers};
+pub use language::{LanguageTag, LanguageTagParseError};
+pub use long_message::{LongMessage, Progress};
+pub use names::EnumParseError;
+use op_lock::{OpGuard, OpLock};
+pub use profile::Profile;
+pub use reconnect::ReconnectPolicy;
+use reconnect::{Destination, OpenParams, OwnedAddress};
+pub use scoped::Scoped;
+pub use settings::Settings;
+pub use sound_icon::{SoundIcon, SoundIconCatalog};
+use ssip::Reply;
+pub use supervisor::{ClientId, Supervisor, Target};
+pub use utterance::Utterance;
+pub use voice::Voice;
+pub use voice_query::{LanguageMatch, SelectionReason, VoiceQuery, VoiceSelection};
+use watchdog::{spawn_keepalive, Watchdog};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum Mode {
     Single = SPDConnectionMode::SPD_MODE_SINGLE,
     Threaded = SPDConnectionMode::SPD_MODE_THREADED,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum Priority {
     Important = SPDPriority::SPD_IMPORTANT,
@@ -25,32 +93,99 @@ pub enum Priority {
     Progress = SPDPriority::SPD_PROGRESS,
 }
 
-#[derive(Clone, Copy, Debug)]
+// `SPDVoiceType` is signed from libspeechd 0.11 on.
+#[allow(clippy::unnecessary_cast)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum VoiceType {
-    Male1 = SPDVoiceType::SPD_MALE1,
-    Male2 = SPDVoiceType::SPD_MALE2,
-    Male3 = SPDVoiceType::SPD_MALE3,
-    Female1 = SPDVoiceType::SPD_FEMALE1,
-    Female2 = SPDVoiceType::SPD_FEMALE2,
-    Female3 = SPDVoiceType::SPD_FEMALE3,
-    ChildMale = SPDVoiceType::SPD_CHILD_MALE,
-    ChildFemale = SPDVoiceType::SPD_CHILD_FEMALE,
+    Male1 = SPDVoiceType::SPD_MALE1 as u32,
+    Male2 = SPDVoiceType::SPD_MALE2 as u32,
+    Male3 = SPDVoiceType::SPD_MALE3 as u32,
+    Female1 = SPDVoiceType::SPD_FEMALE1 as u32,
+    Female2 = SPDVoiceType::SPD_FEMALE2 as u32,
+    Female3 = SPDVoiceType::SPD_FEMALE3 as u32,
+    ChildMale = SPDVoiceType::SPD_CHILD_MALE as u32,
+    ChildFemale = SPDVoiceType::SPD_CHILD_FEMALE as u32,
 }
 
 #[derive(Clone, Debug)]
-pub struct Connection(pub *mut SPDConnection, u64);
+pub struct Connection(Arc<Inner>);
 
-pub type Address = SPDConnectionAddress;
+#[derive(Debug)]
+struct Inner {
+    state: Mutex<State>,
+    ops: OpLock,
+    client_id: AtomicU64,
+}
+
+#[derive(Debug)]
+struct State {
+    raw: *mut SPDConnection,
+    closed: bool,
+    params: OpenParams,
+    settings: Settings,
+    notifications: u32,
+    reconnect: Option<ReconnectPolicy>,
+    failures: u32,
+    retry_at: Option<Instant>,
+    lost: bool,
+    timeout: Option<Duration>,
+    watchdog: Option<Watchdog>,
+    keepalive_generation: u64,
+    in_block: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Notice {
+    Reconnected { old_id: u64, new_id: u64 },
+    ConnectionLost(u64),
+}
+
+impl Notice {
+    /// The client id the callback is called with.
+    fn client_id(self) -> u64 {
+        match self {
+            Notice::Reconnected { new_id, .. } => new_id,
+            Notice::ConnectionLost(id) => id,
+        }
+    }
 
-#[derive(Clone, Copy, Debug)]
+    fn callback(self, c: &mut Callbacks) -> &mut Option<Box<dyn FnMut(u64)>> {
+        match self {
+            Notice::Reconnected { .. } => &mut c.reconnected,
+            Notice::ConnectionLost(_) => &mut c.connection_lost,
+        }
+    }
+}
+
+// libspeechd connections may be used from any thread, and every use goes
+// through the state lock.
+unsafe impl Send for State {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum DataMode {
     Text = SPDDataMode::SPD_DATA_TEXT,
+    #[cfg_attr(feature = "serde", serde(rename = "ssml"))]
     SSML = SPDDataMode::SPD_DATA_SSML,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum Notification {
     Begin = SPDNotification::SPD_BEGIN,
@@ -62,15 +197,73 @@ pub enum Notification {
     All = SPDNotification::SPD_ALL,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// A notification delivered to a client, along with the message and client
+/// it concerns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Begin {
+        msg_id: u64,
+        client_id: u64,
+    },
+    End {
+        msg_id: u64,
+        client_id: u64,
+    },
+    IndexMark {
+        msg_id: u64,
+        client_id: u64,
+        mark: String,
+    },
+    Cancel {
+        msg_id: u64,
+        client_id: u64,
+    },
+    Pause {
+        msg_id: u64,
+        client_id: u64,
+    },
+    Resume {
+        msg_id: u64,
+        client_id: u64,
+    },
+}
+
+impl Event {
+    /// The message the event concerns.
+    pub fn msg_id(&self) -> u64 {
+        match self {
+            Event::Begin { msg_id, .. }
+            | Event::End { msg_id, .. }
+            | Event::IndexMark { msg_id, .. }
+            | Event::Cancel { msg_id, .. }
+            | Event::Pause { msg_id, .. }
+            | Event::Resume { msg_id, .. } => *msg_id,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum Punctuation {
     All = SPDPunctuation::SPD_PUNCT_ALL,
     None = SPDPunctuation::SPD_PUNCT_NONE,
     Some = SPDPunctuation::SPD_PUNCT_SOME,
+    /// Needs libspeechd 0.10.
+    #[cfg(feature = "0_10")]
+    Most = SPDPunctuation::SPD_PUNCT_MOST,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum CapitalLetters {
     None = SPDCapitalLetters::SPD_CAP_NONE,
@@ -82,14 +275,26 @@ fn i32_to_bool(v: i32) -> bool {
     v == 1
 }
 
+type IndexMarkCallback = Box<dyn FnMut(u64, u64, String)>;
+
+/// Sees every event of a client before its callbacks, and is dropped once
+/// it returns `false`.
+type Observer = Box<dyn FnMut(&Event) -> bool>;
+
 #[derive(Default)]
 struct Callbacks {
     begin: Option<Box<dyn FnMut(u64, u64)>>,
     end: Option<Box<dyn FnMut(u64, u64)>>,
-    index_mark: Option<Box<dyn FnMut(u64, u64, String)>>,
+    index_mark: Option<IndexMarkCallback>,
     cancel: Option<Box<dyn FnMut(u64, u64)>>,
     pause: Option<Box<dyn FnMut(u64, u64)>>,
     resume: Option<Box<dyn FnMut(u64, u64)>>,
+    reconnected: Option<Box<dyn FnMut(u64)>>,
+    connection_lost: Option<Box<dyn FnMut(u64)>>,
+    /// Counts changes to `reconnected` and `connection_lost`, which are
+    /// taken out while they run so they can set callbacks themselves.
+    notice_sets: u64,
+    observers: Vec<Observer>,
 }
 
 unsafe impl Send for Callbacks {}
@@ -103,7 +308,8 @@ lazy_static! {
     };
 }
 
-unsafe extern "C" fn cb(msg_id: u64, client_id: u64, state: u32) {
+unsafe extern "C" fn cb(msg_id: usize, client_id: usize, state: u32) {
+    let (msg_id, client_id) = (msg_id as u64, client_id as u64);
     let state = match state {
         SPDNotificationType_SPD_EVENT_BEGIN => Notification::Begin,
         SPDNotificationType_SPD_EVENT_END => Notification::End,
@@ -113,6 +319,14 @@ unsafe extern "C" fn cb(msg_id: u64, client_id: u64, state: u32) {
         _ => panic!("Unknown notification received in callback: {}", state),
     };
     if let Some(c) = callbacks.lock().unwrap().get_mut(&client_id) {
+        let event = match state {
+            Notification::Begin => Event::Begin { msg_id, client_id },
+            Notification::End => Event::End { msg_id, client_id },
+            Notification::Cancel => Event::Cancel { msg_id, client_id },
+            Notification::Pause => Event::Pause { msg_id, client_id },
+            _ => Event::Resume { msg_id, client_id },
+        };
+        c.observers.retain_mut(|f| f(&event));
         let f = match state {
             Notification::Begin => &mut c.begin,
             Notification::End => &mut c.end,
@@ -127,7 +341,8 @@ unsafe extern "C" fn cb(msg_id: u64, client_id: u64, state: u32) {
     }
 }
 
-unsafe extern "C" fn cb_im(msg_id: u64, client_id: u64, state: u32, index_mark: *mut i8) {
+unsafe extern "C" fn cb_im(msg_id: usize, client_id: usize, state: u32, index_mark: *mut c_char) {
+    let (msg_id, client_id) = (msg_id as u64, client_id as u64);
     let index_mark = CStr::from_ptr(index_mark);
     let index_mark = index_mark.to_string_lossy().to_string();
     let state = match state {
@@ -135,6 +350,12 @@ unsafe extern "C" fn cb_im(msg_id: u64, client_id: u64, state: u32, index_mark:
         _ => panic!("Unknown notification received in IM callback: {}", state),
     };
     if let Some(c) = callbacks.lock().unwrap().get_mut(&client_id) {
+        let event = Event::IndexMark {
+            msg_id,
+            client_id,
+            mark: index_mark.clone(),
+        };
+        c.observers.retain_mut(|f| f(&event));
         let f = match state {
             Notification::IndexMarks => &mut c.index_mark,
             _ => panic!("Unknown notification type"),
@@ -145,96 +366,340 @@ unsafe extern "C" fn cb_im(msg_id: u64, client_id: u64, state: u32, index_mark:
     }
 }
 
+/// Sends raw SSIP and waits for the reply, which is `None` if there was
+/// none.
+unsafe fn send_raw(c: *mut SPDConnection, data: &CStr) -> Option<String> {
+    let reply = spd_send_data(c, data.as_ptr(), SPD_WAIT_REPLY as i32);
+    if reply.is_null() {
+        return None;
+    }
+    let text = CStr::from_ptr(reply).to_string_lossy().to_string();
+    libc::free(reply as *mut libc::c_void);
+    Some(text)
+}
+
+/// Routes the events of a new libspeechd connection to the callbacks,
+/// subscribes to all of them and returns the client id.
+unsafe fn setup(c: *mut SPDConnection) -> u64 {
+    if c.is_null() {
+        return 0;
+    }
+    (*c).callback_begin = Some(cb);
+    (*c).callback_end = Some(cb);
+    (*c).callback_cancel = Some(cb);
+    (*c).callback_pause = Some(cb);
+    (*c).callback_resume = Some(cb);
+    (*c).callback_im = Some(cb_im);
+    let client_id = {
+        let data = CString::new("HISTORY GET CLIENT_ID\r\n").unwrap();
+        let reply = spd_send_data(c, data.as_ptr(), SPD_WAIT_REPLY as i32);
+        if reply.is_null() {
+            0
+        } else {
+            let id = CStr::from_ptr(reply)
+                .to_string_lossy()
+                .split("\r\n")
+                .next()
+                .and_then(|line| line.split('-').nth(1))
+                .and_then(|id| id.parse().ok())
+                .unwrap_or(0);
+            libc::free(reply as *mut libc::c_void);
+            id
+        }
+    };
+    spd_set_notification_on(c, Notification::All as u32);
+    client_id
+}
+
 impl Connection {
+    /// Connects to the default server, starting it if needed. Fails with
+    /// `Error::LibraryUnavailable` if libspeechd can't be loaded, which
+    /// only happens with the `dynamic` feature, or `Error::Disconnected` if
+    /// no connection could be made.
     pub fn open<S: Into<String>>(
         client_name: S,
         connection_name: S,
         user_name: S,
         mode: Mode,
-    ) -> Self {
-        let clientname = CString::new(client_name.into()).unwrap();
-        let connectionname = CString::new(connection_name.into()).unwrap();
-        let username = CString::new(user_name.into()).unwrap();
-        let connection = unsafe {
-            let c = spd_open(
-                clientname.as_ptr(),
-                connectionname.as_ptr(),
-                username.as_ptr(),
-                mode as u32,
-            );
-            Self::setup_connection(c)
-        };
-        let mut c = Self(connection, 0);
-        c.setup();
-        c
-    }
-
+    ) -> Result<Self, Error> {
+        Self::open_with(OpenParams {
+            client_name: CString::new(client_name.into()).unwrap(),
+            connection_name: CString::new(connection_name.into()).unwrap(),
+            user_name: CString::new(user_name.into()).unwrap(),
+            mode,
+            address: None,
+            autospawn: true,
+            connect_timeout: None,
+        })
+    }
+
+    /// # Safety
+    ///
+    /// `address` must be null or point to a valid `SPDConnectionAddress`.
+    /// It is only read during this call.
     pub unsafe fn open2<S: Into<String>>(
         client_name: S,
         connection_name: S,
         user_name: S,
         mode: Mode,
-        address: *mut Address,
+        address: *mut SPDConnectionAddress,
         autospawn: bool,
-    ) -> Self {
-        let auto_spawn = if autospawn { 1 } else { 0 };
-        let error_result = vec![CString::new("").unwrap().into_raw()].as_mut_ptr();
-        let clientname = CString::new(client_name.into()).unwrap();
-        let connectionname = CString::new(connection_name.into()).unwrap();
-        let username = CString::new(user_name.into()).unwrap();
-        let connection = {
-            let c = spd_open2(
-                clientname.as_ptr(),
-                connectionname.as_ptr(),
-                username.as_ptr(),
-                mode as u32,
-                address,
-                auto_spawn,
-                error_result,
-            );
-            Self::setup_connection(c)
+    ) -> Result<Self, Error> {
+        Self::open_with(OpenParams {
+            client_name: CString::new(client_name.into()).unwrap(),
+            connection_name: CString::new(connection_name.into()).unwrap(),
+            user_name: CString::new(user_name.into()).unwrap(),
+            mode,
+            address: address
+                .as_ref()
+                .map(|a| Destination::Raw(OwnedAddress::from_raw(a))),
+            autospawn,
+            connect_timeout: None,
+        })
+    }
+
+    /// Connects to the server at `address` rather than the default one.
+    ///
+    /// libspeechd only accepts IPv4 literals, so hostnames are resolved
+    /// here, again on every reconnect. IPv6 addresses, and hostnames with
+    /// only IPv6 addresses, fail with `Error::Unsupported(Feature::Ipv6)`.
+    /// To reach a server over IPv6, connect with `Address::connect` and use
+    /// `AsyncConnection` instead.
+    ///
+    /// `connect_timeout` bounds how long opening the connection may take,
+    /// reconnects included, after which it fails with `Error::Timeout`.
+    pub fn open_address<S: Into<String>>(
+        client_name: S,
+        connection_name: S,
+        user_name: S,
+        mode: Mode,
+        address: &Address,
+        connect_timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        reconnect::check_ipv4(address)?;
+        Self::open_with(OpenParams {
+            client_name: CString::new(client_name.into()).unwrap(),
+            connection_name: CString::new(connection_name.into()).unwrap(),
+            user_name: CString::new(user_name.into()).unwrap(),
+            mode,
+            address: Some(Destination::Named(address.clone())),
+            autospawn: false,
+            connect_timeout,
+        })
+    }
+
+    fn open_with(params: OpenParams) -> Result<Self, Error> {
+        #[cfg(feature = "dynamic")]
+        speech_dispatcher_sys::load().map_err(|e| Error::LibraryUnavailable(e.to_string()))?;
+        let raw = params.open()?;
+        let client_id = unsafe { setup(raw) };
+        Ok(Self::from_raw(raw, params, client_id))
+    }
+
+    /// Opens another client of the same server, with this one's names,
+    /// mode, timeout and settings, for messages that must be controlled
+    /// apart from this connection's.
+    fn open_sibling(&self) -> Result<Self, Error> {
+        let (params, settings, timeout) = {
+            let state = self.state();
+            (state.params.clone(), state.settings.clone(), state.timeout)
         };
-        let mut c = Self(connection, 0);
-        c.setup();
-        c
-    }
-
-    unsafe fn setup_connection(c: *mut SPDConnection) -> *mut SPDConnection {
-        (*c).callback_begin = Some(cb);
-        (*c).callback_end = Some(cb);
-        (*c).callback_cancel = Some(cb);
-        (*c).callback_pause = Some(cb);
-        (*c).callback_resume = Some(cb);
-        (*c).callback_im = Some(cb_im);
-        c
-    }
-
-    fn setup(&mut self) {
-        let client_id = self.send_data("HISTORY GET CLIENT_ID\r\n", true);
-        if let Some(client_id) = client_id {
-            let client_id: Vec<&str> = client_id.split("\r\n").collect();
-            let client_id = client_id.get(0);
-            if let Some(client_id) = client_id {
-                let client_id: Vec<&str> = client_id.split("-").collect();
-                if let Some(client_id) = client_id.get(1) {
-                    if let Ok(client_id) = client_id.parse::<u64>() {
-                        self.1 = client_id;
+        let sibling = Self::open_with(params)?;
+        {
+            let mut state = sibling.state();
+            state.settings = settings;
+            state.timeout = timeout;
+            state.replay();
+        }
+        Ok(sibling)
+    }
+
+    fn from_raw(raw: *mut SPDConnection, params: OpenParams, client_id: u64) -> Self {
+        callbacks
+            .lock()
+            .unwrap()
+            .insert(client_id, Default::default());
+        Self(Arc::new(Inner {
+            state: Mutex::new(State {
+                raw,
+                closed: false,
+                params,
+                settings: Default::default(),
+                notifications: Notification::All as u32,
+                reconnect: None,
+                failures: 0,
+                retry_at: None,
+                lost: false,
+                timeout: None,
+                watchdog: None,
+                keepalive_generation: 0,
+                in_block: false,
+            }),
+            ops: OpLock::default(),
+            client_id: AtomicU64::new(client_id),
+        }))
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.state.lock().unwrap()
+    }
+
+    /// Keeps other threads from using the connection until the guard is
+    /// dropped. Calls from this thread still go through.
+    fn serialize(&self) -> OpGuard<'_> {
+        self.0.ops.lock()
+    }
+
+    /// Runs `f` against the underlying libspeechd connection. If `failed`
+    /// says it didn't go through because the server went away, the
+    /// connection is reopened according to the reconnect policy and `f` is
+    /// tried once more.
+    fn call<T>(
+        &self,
+        f: impl Fn(*mut SPDConnection) -> T,
+        failed: impl Fn(&T) -> bool,
+    ) -> Result<T, Error> {
+        let _op = self.serialize();
+        let state = self.state();
+        let timeout = state.timeout;
+        self.call_locked(state, timeout, f, failed)
+    }
+
+    fn call_locked<T>(
+        &self,
+        mut state: MutexGuard<'_, State>,
+        timeout: Option<Duration>,
+        f: impl Fn(*mut SPDConnection) -> T,
+        failed: impl Fn(&T) -> bool,
+    ) -> Result<T, Error> {
+        let mut notice = None;
+        let v = state.run(&self.0.client_id, timeout, &f, &failed, &mut notice);
+        drop(state);
+        if let Some(notice) = notice {
+            let client_id = notice.client_id();
+            // The callback runs without the callbacks lock, which it may
+            // need, and goes back unless it set a new one meanwhile.
+            let taken = {
+                let mut cbs = callbacks.lock().unwrap();
+                if let Notice::Reconnected { old_id, new_id } = notice {
+                    let entry = cbs.remove(&old_id).unwrap_or_default();
+                    cbs.insert(new_id, entry);
+                }
+                cbs.get_mut(&client_id)
+                    .and_then(|c| Some((notice.callback(c).take()?, c.notice_sets)))
+            };
+            if let Some((mut f, sets)) = taken {
+                f(client_id);
+                let mut cbs = callbacks.lock().unwrap();
+                if let Some(c) = cbs.get_mut(&self.client_id().0) {
+                    if c.notice_sets == sets {
+                        *notice.callback(c) = Some(f);
                     }
                 }
             }
         }
-        callbacks.lock().unwrap().insert(self.1, Default::default());
-        self.set_notification_on(Notification::All);
+        v
+    }
+
+    fn call_int(&self, f: impl Fn(*mut SPDConnection) -> i32) -> i32 {
+        self.call(f, |v| *v == -1).unwrap_or(-1)
+    }
+
+    fn call_ptr<T>(&self, f: impl Fn(*mut SPDConnection) -> *mut T) -> *mut T {
+        self.call(f, |v| v.is_null()).unwrap_or(ptr::null_mut())
+    }
+
+    fn ping(&self, interval: Duration) {
+        // Another thread may be between two commands that must go together,
+        // such as an utterance's settings and its SPEAK. The connection is in
+        // use then anyway, so the ping is skipped rather than slipped in
+        // between.
+        let _op = match self.0.ops.try_lock() {
+            Some(op) => op,
+            None => return,
+        };
+        let state = self.state();
+        let timeout = state.timeout.unwrap_or(interval);
+        let data = CString::new("HISTORY GET CLIENT_ID\r\n").unwrap();
+        let reply = self.call_locked(
+            state,
+            Some(timeout),
+            |c| unsafe { spd_send_data(c, data.as_ptr(), SPD_WAIT_REPLY as i32) },
+            |v| v.is_null(),
+        );
+        if let Ok(reply) = reply {
+            if !reply.is_null() {
+                unsafe { libc::free(reply as *mut libc::c_void) };
+            }
+        }
+    }
+
+    /// Updates the settings replayed after a reconnect if libspeechd
+    /// reported success.
+    fn record(&self, v: i32, f: impl FnOnce(&mut Settings)) {
+        if v == 0 {
+            f(&mut self.state().settings);
+        }
     }
 
     pub fn close(&self) {
-        unsafe { spd_close(self.0) };
+        let mut state = self.state();
+        if !state.closed && !state.raw.is_null() {
+            unsafe { spd_close(state.raw) };
+        }
+        state.raw = ptr::null_mut();
+        state.closed = true;
+    }
+
+    /// Reopens the connection when the server goes away, for instance after
+    /// a crash or an idle timeout. `None`, the default, disables it.
+    pub fn set_reconnect_policy(&self, policy: Option<ReconnectPolicy>) {
+        let mut state = self.state();
+        state.reconnect = policy;
+        state.failures = 0;
+        state.retry_at = None;
+    }
+
+    /// Gives up on commands the server doesn't answer within `timeout`.
+    /// The connection is closed when that happens, since a late reply would
+    /// be taken for the answer to the next command; with a reconnect policy
+    /// the next call reopens it. `None`, the default, waits forever.
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        let mut state = self.state();
+        state.timeout = timeout;
+        if timeout.is_some() && state.watchdog.is_none() {
+            state.watchdog = Some(Watchdog::new());
+        }
+    }
+
+    /// Pings the server every `interval` and reports `on_connection_lost`
+    /// when it stops answering. Pings use the command timeout, or the
+    /// interval if there is none. `None`, the default, disables it.
+    pub fn set_keepalive(&self, interval: Option<Duration>) {
+        let mut state = self.state();
+        state.keepalive_generation += 1;
+        if let Some(interval) = interval {
+            if state.watchdog.is_none() {
+                state.watchdog = Some(Watchdog::new());
+            }
+            spawn_keepalive(
+                Arc::downgrade(&self.0),
+                interval,
+                state.keepalive_generation,
+            );
+        }
+    }
+
+    /// What this connection has set so far. Settings it never changed are
+    /// `None`, meaning they are at the server's defaults.
+    pub fn settings(&self) -> Settings {
+        self.state().settings.clone()
     }
 
     pub fn say<S: Into<String>>(&self, priority: Priority, text: S) -> Option<u64> {
         let text: String = text.into();
         let param = CString::new(text).unwrap();
-        let rv = unsafe { spd_say(self.0, priority as u32, param.as_ptr()) };
+        let rv = self.call_int(|c| unsafe { spd_say(c, priority as u32, param.as_ptr()) });
         if rv != -1 {
             Some(rv as u64)
         } else {
@@ -245,7 +710,7 @@ impl Connection {
     pub fn sayf<S: Into<String>>(&self, priority: Priority, format: S) -> Option<i32> {
         let format: String = format.into();
         let param = CString::new(format).unwrap();
-        let rv = unsafe { spd_sayf(self.0, priority as u32, param.as_ptr()) };
+        let rv = self.call_int(|c| unsafe { spd_sayf(c, priority as u32, param.as_ptr()) });
         if rv != -1 {
             Some(rv)
         } else {
@@ -254,106 +719,130 @@ impl Connection {
     }
 
     pub fn stop(&self) -> bool {
-        let v = unsafe { spd_stop(self.0) };
+        let v = self.call_int(|c| unsafe { spd_stop(c) });
         i32_to_bool(v)
     }
 
     pub fn stop_all(&self) -> bool {
-        let v = unsafe { spd_stop_all(self.0) };
+        let v = self.call_int(|c| unsafe { spd_stop_all(c) });
         i32_to_bool(v)
     }
 
     pub fn stop_uid(&self, target_uid: i32) -> bool {
-        let v = unsafe { spd_stop_uid(self.0, target_uid) };
+        let v = self.call_int(|c| unsafe { spd_stop_uid(c, target_uid) });
         i32_to_bool(v)
     }
 
     pub fn cancel(&self) -> bool {
-        let v = unsafe { spd_cancel(self.0) };
+        let v = self.call_int(|c| unsafe { spd_cancel(c) });
         i32_to_bool(v)
     }
 
     pub fn cancel_all(&self) -> bool {
-        let v = unsafe { spd_cancel_all(self.0) };
+        let v = self.call_int(|c| unsafe { spd_cancel_all(c) });
         i32_to_bool(v)
     }
 
     pub fn cancel_uid(&self, target_uid: i32) -> bool {
-        let v = unsafe { spd_cancel_uid(self.0, target_uid) };
+        let v = self.call_int(|c| unsafe { spd_cancel_uid(c, target_uid) });
         i32_to_bool(v)
     }
 
     pub fn pause(&self) -> bool {
-        let v = unsafe { spd_pause(self.0) };
+        let v = self.call_int(|c| unsafe { spd_pause(c) });
         i32_to_bool(v)
     }
 
     pub fn pause_all(&self) -> bool {
-        let v = unsafe { spd_pause_all(self.0) };
+        let v = self.call_int(|c| unsafe { spd_pause_all(c) });
         i32_to_bool(v)
     }
 
     pub fn pause_uid(&self, target_uid: i32) -> bool {
-        let v = unsafe { spd_pause_uid(self.0, target_uid) };
+        let v = self.call_int(|c| unsafe { spd_pause_uid(c, target_uid) });
         i32_to_bool(v)
     }
 
     pub fn resume(&self) -> bool {
-        let v = unsafe { spd_resume(self.0) };
+        let v = self.call_int(|c| unsafe { spd_resume(c) });
         i32_to_bool(v)
     }
 
     pub fn resume_all(&self) -> bool {
-        let v = unsafe { spd_resume_all(self.0) };
+        let v = self.call_int(|c| unsafe { spd_resume_all(c) });
         i32_to_bool(v)
     }
 
     pub fn resume_uid(&self, target_uid: i32) -> bool {
-        let v = unsafe { spd_resume_uid(self.0, target_uid) };
+        let v = self.call_int(|c| unsafe { spd_resume_uid(c, target_uid) });
         i32_to_bool(v)
     }
 
-    pub fn key<S: Into<String>>(&self, priority: Priority, key_name: S) -> bool {
-        let param = CString::new(key_name.into()).unwrap();
-        let v = unsafe { spd_key(self.0, priority as u32, param.as_ptr()) };
+    pub fn key(&self, priority: Priority, key: &Key) -> bool {
+        let param = CString::new(key.to_string()).unwrap();
+        let v = self.call_int(|c| unsafe { spd_key(c, priority as u32, param.as_ptr()) });
         i32_to_bool(v)
     }
 
     pub fn char<S: Into<String>>(&self, priority: Priority, char: S) -> bool {
         let param = CString::new(char.into()).unwrap();
-        let v = unsafe { spd_char(self.0, priority as u32, param.as_ptr()) };
+        let v = self.call_int(|c| unsafe { spd_char(c, priority as u32, param.as_ptr()) });
         i32_to_bool(v)
     }
 
     pub fn wchar(&self, priority: Priority, wchar: i32) -> bool {
-        let v = unsafe { spd_wchar(self.0, priority as u32, wchar) };
+        let v = self.call_int(|c| unsafe { spd_wchar(c, priority as u32, wchar) });
         i32_to_bool(v)
     }
 
-    pub fn sound_icon<S: Into<String>>(&self, priority: Priority, icon_name: S) -> bool {
-        let param = CString::new(icon_name.into()).unwrap();
-        let v = unsafe { spd_char(self.0, priority as u32, param.as_ptr()) };
-        i32_to_bool(v)
+    /// Plays an icon from the folders `SoundIconCatalog::discover` finds,
+    /// by file name or by the name without its extension. Fails without
+    /// sending anything with `Error::InvalidSoundIcon` if the name isn't a
+    /// valid `SoundIcon`, or with `Error::UnknownSoundIcon` if no such icon
+    /// is installed. Names aren't checked if no icons are found, as when
+    /// they are only installed where the server runs.
+    pub fn sound_icon<S: Into<String>>(
+        &self,
+        priority: Priority,
+        icon_name: S,
+    ) -> Result<bool, Error> {
+        let icon = sound_icon::resolve_discovered(icon_name.into())?;
+        let param = CString::new(String::from(icon)).unwrap();
+        let v = self.call_int(|c| unsafe { spd_sound_icon(c, priority as u32, param.as_ptr()) });
+        Ok(i32_to_bool(v))
     }
 
     pub fn set_voice_type(&self, voice_type: VoiceType) -> bool {
-        let v = unsafe { spd_set_voice_type(self.0, voice_type as u32) };
+        let v =
+            self.call_int(|c| unsafe { spd_set_voice_type(c, voice_type as SPDVoiceType::Type) });
+        self.record(v, |s| {
+            s.voice_type = Some(voice_type);
+            s.synthesis_voice = None;
+        });
         i32_to_bool(v)
     }
 
     pub fn set_voice_type_all(&self, voice_type: VoiceType) -> bool {
-        let v = unsafe { spd_set_voice_type_all(self.0, voice_type as u32) };
+        let v = self
+            .call_int(|c| unsafe { spd_set_voice_type_all(c, voice_type as SPDVoiceType::Type) });
         i32_to_bool(v)
     }
 
     pub fn set_voice_type_uid(&self, voice_type: VoiceType, target_uid: u32) -> bool {
-        let v = unsafe { spd_set_voice_type_uid(self.0, voice_type as u32, target_uid) };
+        let v = self.call_int(|c| unsafe {
+            spd_set_voice_type_uid(c, voice_type as SPDVoiceType::Type, target_uid)
+        });
         i32_to_bool(v)
     }
 
-    pub fn get_voice_type(&self) -> VoiceType {
-        let v = unsafe { spd_get_voice_type(self.0) };
-        match v {
+    /// The voice type in use. `None` if the connection is closed, lost or
+    /// timed out, or if the server reports none, as 0.11 does with
+    /// `SPD_UNSPECIFIED` once a synthesis voice was chosen by name.
+    pub fn get_voice_type(&self) -> Option<VoiceType> {
+        let v = self
+            .call(|c| unsafe { spd_get_voice_type(c) }, |_| false)
+            .ok()?;
+        let voice_type = match v {
             SPDVoiceType::SPD_MALE1 => VoiceType::Male1,
             SPDVoiceType::SPD_MALE2 => VoiceType::Male2,
             SPDVoiceType::SPD_MALE3 => VoiceType::Male3,
@@ -362,128 +851,187 @@ impl Connection {
             SPDVoiceType::SPD_FEMALE3 => VoiceType::Female3,
             SPDVoiceType::SPD_CHILD_MALE => VoiceType::ChildMale,
             SPDVoiceType::SPD_CHILD_FEMALE => VoiceType::ChildFemale,
-            _ => panic!("Invalid voice type"),
-        }
+            _ => return None,
+        };
+        Some(voice_type)
     }
 
     pub fn set_synthesis_voice<S: Into<String>>(&self, voice_name: S) -> bool {
-        let param = CString::new(voice_name.into()).unwrap();
-        let v = unsafe { spd_set_synthesis_voice(self.0, param.as_ptr()) };
+        let voice_name: String = voice_name.into();
+        let param = CString::new(voice_name.as_str()).unwrap();
+        let v = self.call_int(|c| unsafe { spd_set_synthesis_voice(c, param.as_ptr()) });
+        self.record(v, |s| {
+            s.synthesis_voice = Some(voice_name);
+            s.voice_type = None;
+        });
         i32_to_bool(v)
     }
 
     pub fn set_synthesis_voice_all<S: Into<String>>(&self, voice_name: S) -> bool {
         let param = CString::new(voice_name.into()).unwrap();
-        let v = unsafe { spd_set_synthesis_voice_all(self.0, param.as_ptr()) };
+        let v = self.call_int(|c| unsafe { spd_set_synthesis_voice_all(c, param.as_ptr()) });
         i32_to_bool(v)
     }
 
     pub fn set_synthesis_voice_uid<S: Into<String>>(&self, voice_name: S, target_uid: u32) -> bool {
         let param = CString::new(voice_name.into()).unwrap();
-        let v = unsafe { spd_set_synthesis_voice_uid(self.0, param.as_ptr(), target_uid) };
+        let v = self
+            .call_int(|c| unsafe { spd_set_synthesis_voice_uid(c, param.as_ptr(), target_uid) });
         i32_to_bool(v)
     }
 
     pub fn set_data_mode(&self, mode: DataMode) -> bool {
-        let v = unsafe { spd_set_data_mode(self.0, mode as u32) };
+        let v = self.call_int(|c| unsafe { spd_set_data_mode(c, mode as u32) });
+        self.record(v, |s| s.data_mode = Some(mode));
         i32_to_bool(v)
     }
 
     pub fn set_notification_on(&self, notification: Notification) -> bool {
-        let v = unsafe { spd_set_notification_on(self.0, notification as u32) };
+        let v = self.call_int(|c| unsafe { spd_set_notification_on(c, notification as u32) });
+        if v == 0 {
+            self.state().notifications |= notification as u32;
+        }
         i32_to_bool(v)
     }
 
     pub fn set_notification_off(&self, notification: Notification) -> bool {
-        let v = unsafe { spd_set_notification_off(self.0, notification as u32) };
+        let v = self.call_int(|c| unsafe { spd_set_notification_off(c, notification as u32) });
+        if v == 0 {
+            self.state().notifications &= !(notification as u32);
+        }
         i32_to_bool(v)
     }
 
     pub fn set_notification<S: Into<String>>(&self, notification: Notification, state: S) -> bool {
-        let param = CString::new(state.into()).unwrap();
-        let v = unsafe { spd_set_notification(self.0, notification as u32, param.as_ptr()) };
+        let state: String = state.into();
+        let param = CString::new(state.as_str()).unwrap();
+        let v = self
+            .call_int(|c| unsafe { spd_set_notification(c, notification as u32, param.as_ptr()) });
+        if v == 0 {
+            match state.as_str() {
+                "on" => self.state().notifications |= notification as u32,
+                "off" => self.state().notifications &= !(notification as u32),
+                _ => (),
+            }
+        }
         i32_to_bool(v)
     }
 
     pub fn set_voice_rate(&self, rate: i32) -> bool {
-        let v = unsafe { spd_set_voice_rate(self.0, rate) };
+        let v = self.call_int(|c| unsafe { spd_set_voice_rate(c, rate) });
+        self.record(v, |s| s.rate = Some(rate));
         i32_to_bool(v)
     }
 
     pub fn set_voice_rate_all(&self, rate: i32) -> bool {
-        let v = unsafe { spd_set_voice_rate_all(self.0, rate) };
+        let v = self.call_int(|c| unsafe { spd_set_voice_rate_all(c, rate) });
         i32_to_bool(v)
     }
 
     pub fn set_voice_rate_uid(&self, rate: i32, target_uid: u32) -> bool {
-        let v = unsafe { spd_set_voice_rate_uid(self.0, rate, target_uid) };
+        let v = self.call_int(|c| unsafe { spd_set_voice_rate_uid(c, rate, target_uid) });
         i32_to_bool(v)
     }
 
     pub fn get_voice_rate(&self) -> i32 {
-        unsafe { spd_get_voice_rate(self.0) }
+        self.call_int(|c| unsafe { spd_get_voice_rate(c) })
     }
 
     pub fn set_voice_pitch(&self, pitch: i32) -> bool {
-        let v = unsafe { spd_set_voice_pitch(self.0, pitch) };
+        let v = self.call_int(|c| unsafe { spd_set_voice_pitch(c, pitch) });
+        self.record(v, |s| s.pitch = Some(pitch));
         i32_to_bool(v)
     }
 
     pub fn set_voice_pitch_all(&self, pitch: i32) -> bool {
-        let v = unsafe { spd_set_voice_pitch_all(self.0, pitch) };
+        let v = self.call_int(|c| unsafe { spd_set_voice_pitch_all(c, pitch) });
         i32_to_bool(v)
     }
 
     pub fn set_voice_pitch_uid(&self, pitch: i32, target_uid: u32) -> bool {
-        let v = unsafe { spd_set_voice_pitch_uid(self.0, pitch, target_uid) };
+        let v = self.call_int(|c| unsafe { spd_set_voice_pitch_uid(c, pitch, target_uid) });
         i32_to_bool(v)
     }
 
     pub fn get_voice_pitch(&self) -> i32 {
-        unsafe { spd_get_voice_pitch(self.0) }
+        self.call_int(|c| unsafe { spd_get_voice_pitch(c) })
+    }
+
+    /// Sets how much the pitch varies while speaking, from -100 to 100.
+    /// Needs libspeechd 0.11, and fails with `Error::Unsupported` without it.
+    #[cfg(feature = "0_11")]
+    pub fn set_voice_pitch_range(&self, pitch_range: i32) -> Result<bool, Error> {
+        Feature::PitchRange.require()?;
+        let v = self.call_int(|c| unsafe { spd_set_voice_pitch_range(c, pitch_range) });
+        self.record(v, |s| s.pitch_range = Some(pitch_range));
+        Ok(i32_to_bool(v))
+    }
+
+    #[cfg(feature = "0_11")]
+    pub fn set_voice_pitch_range_all(&self, pitch_range: i32) -> Result<bool, Error> {
+        Feature::PitchRange.require()?;
+        let v = self.call_int(|c| unsafe { spd_set_voice_pitch_range_all(c, pitch_range) });
+        Ok(i32_to_bool(v))
+    }
+
+    #[cfg(feature = "0_11")]
+    pub fn set_voice_pitch_range_uid(
+        &self,
+        pitch_range: i32,
+        target_uid: u32,
+    ) -> Result<bool, Error> {
+        Feature::PitchRange.require()?;
+        let v =
+            self.call_int(|c| unsafe { spd_set_voice_pitch_range_uid(c, pitch_range, target_uid) });
+        Ok(i32_to_bool(v))
     }
 
     pub fn set_volume(&self, volume: i32) -> bool {
-        let v = unsafe { spd_set_volume(self.0, volume) };
+        let v = self.call_int(|c| unsafe { spd_set_volume(c, volume) });
+        self.record(v, |s| s.volume = Some(volume));
         i32_to_bool(v)
     }
 
     pub fn set_volume_all(&self, volume: i32) -> bool {
-        let v = unsafe { spd_set_volume_all(self.0, volume) };
+        let v = self.call_int(|c| unsafe { spd_set_volume_all(c, volume) });
         i32_to_bool(v)
     }
 
     pub fn set_volume_uid(&self, volume: i32, target_uid: u32) -> bool {
-        let v = unsafe { spd_set_volume_uid(self.0, volume, target_uid) };
+        let v = self.call_int(|c| unsafe { spd_set_volume_uid(c, volume, target_uid) });
         i32_to_bool(v)
     }
 
     pub fn get_volume(&self) -> i32 {
-        unsafe { spd_get_volume(self.0) }
+        self.call_int(|c| unsafe { spd_get_volume(c) })
     }
 
     pub fn set_punctuation(&self, punctuation: Punctuation) -> bool {
-        let v = unsafe { spd_set_punctuation(self.0, punctuation as u32) };
+        let v = self.call_int(|c| unsafe { spd_set_punctuation(c, punctuation as u32) });
+        self.record(v, |s| s.punctuation = Some(punctuation));
         i32_to_bool(v)
     }
 
     pub fn set_punctuation_all(&self, punctuation: Punctuation) -> bool {
-        let v = unsafe { spd_set_punctuation_all(self.0, punctuation as u32) };
+        let v = self.call_int(|c| unsafe { spd_set_punctuation_all(c, punctuation as u32) });
         i32_to_bool(v)
     }
 
     pub fn set_punctuation_uid(&self, punctuation: Punctuation, target_uid: u32) -> bool {
-        let v = unsafe { spd_set_punctuation_uid(self.0, punctuation as u32, target_uid) };
+        let v = self
+            .call_int(|c| unsafe { spd_set_punctuation_uid(c, punctuation as u32, target_uid) });
         i32_to_bool(v)
     }
 
     pub fn set_capital_letters(&self, capital_letters: CapitalLetters) -> bool {
-        let v = unsafe { spd_set_capital_letters(self.0, capital_letters as u32) };
+        let v = self.call_int(|c| unsafe { spd_set_capital_letters(c, capital_letters as u32) });
+        self.record(v, |s| s.capital_letters = Some(capital_letters));
         i32_to_bool(v)
     }
 
     pub fn set_capital_letters_all(&self, capital_letters: CapitalLetters) -> bool {
-        let v = unsafe { spd_set_capital_letters_all(self.0, capital_letters as u32) };
+        let v =
+            self.call_int(|c| unsafe { spd_set_capital_letters_all(c, capital_letters as u32) });
         i32_to_bool(v)
     }
 
@@ -492,7 +1040,9 @@ impl Connection {
         capital_letters: CapitalLetters,
         target_uid: u32,
     ) -> bool {
-        let v = unsafe { spd_set_capital_letters_uid(self.0, capital_letters as u32, target_uid) };
+        let v = self.call_int(|c| unsafe {
+            spd_set_capital_letters_uid(c, capital_letters as u32, target_uid)
+        });
         i32_to_bool(v)
     }
 
@@ -502,7 +1052,8 @@ impl Connection {
         } else {
             SPDSpelling::SPD_SPELL_OFF
         };
-        let v = unsafe { spd_set_spelling(self.0, s) };
+        let v = self.call_int(|c| unsafe { spd_set_spelling(c, s) });
+        self.record(v, |s| s.spelling = Some(spelling));
         i32_to_bool(v)
     }
 
@@ -512,7 +1063,7 @@ impl Connection {
         } else {
             SPDSpelling::SPD_SPELL_OFF
         };
-        let v = unsafe { spd_set_spelling_all(self.0, s) };
+        let v = self.call_int(|c| unsafe { spd_set_spelling_all(c, s) });
         i32_to_bool(v)
     }
 
@@ -522,42 +1073,52 @@ impl Connection {
         } else {
             SPDSpelling::SPD_SPELL_OFF
         };
-        let v = unsafe { spd_set_spelling_uid(self.0, s, target_uid) };
+        let v = self.call_int(|c| unsafe { spd_set_spelling_uid(c, s, target_uid) });
         i32_to_bool(v)
     }
 
-    pub fn set_language<S: Into<String>>(&self, language: S) -> bool {
-        let param = CString::new(language.into()).unwrap();
-        let v = unsafe { spd_set_language(self.0, param.as_ptr()) };
+    pub fn set_language(&self, language: &LanguageTag) -> bool {
+        let param = CString::new(language.as_str()).unwrap();
+        let v = self.call_int(|c| unsafe { spd_set_language(c, param.as_ptr()) });
+        self.record(v, |s| s.language = Some(language.clone()));
         i32_to_bool(v)
     }
 
-    pub fn set_language_all<S: Into<String>>(&self, language: S) -> bool {
-        let param = CString::new(language.into()).unwrap();
-        let v = unsafe { spd_set_language_all(self.0, param.as_ptr()) };
+    pub fn set_language_all(&self, language: &LanguageTag) -> bool {
+        let param = CString::new(language.as_str()).unwrap();
+        let v = self.call_int(|c| unsafe { spd_set_language_all(c, param.as_ptr()) });
         i32_to_bool(v)
     }
 
-    pub fn set_language_uid<S: Into<String>>(&self, language: S, target_uid: u32) -> bool {
-        let param = CString::new(language.into()).unwrap();
-        let v = unsafe { spd_set_language_uid(self.0, param.as_ptr(), target_uid) };
+    pub fn set_language_uid(&self, language: &LanguageTag, target_uid: u32) -> bool {
+        let param = CString::new(language.as_str()).unwrap();
+        let v = self.call_int(|c| unsafe { spd_set_language_uid(c, param.as_ptr(), target_uid) });
         i32_to_bool(v)
     }
 
-    pub fn get_language(&self) -> &str {
-        let v = unsafe { CStr::from_ptr(spd_get_language(self.0)) };
-        v.to_str().unwrap()
+    /// The current language, or `None` if the server doesn't give one that
+    /// parses as a tag.
+    pub fn get_language(&self) -> Option<LanguageTag> {
+        let v = self.call_ptr(|c| unsafe { spd_get_language(c) });
+        if v.is_null() {
+            return None;
+        }
+        let language = unsafe { CStr::from_ptr(v) }.to_string_lossy().parse().ok();
+        unsafe { libc::free(v as *mut libc::c_void) };
+        language
     }
 
     pub fn set_output_module<S: Into<String>>(&self, output_module: S) -> bool {
-        let param = CString::new(output_module.into()).unwrap();
-        let v = unsafe { spd_set_output_module(self.0, param.as_ptr()) };
+        let output_module: String = output_module.into();
+        let param = CString::new(output_module.as_str()).unwrap();
+        let v = self.call_int(|c| unsafe { spd_set_output_module(c, param.as_ptr()) });
+        self.record(v, |s| s.output_module = Some(output_module));
         i32_to_bool(v)
     }
 
     pub fn set_output_module_all<S: Into<String>>(&self, output_module: S) -> bool {
         let param = CString::new(output_module.into()).unwrap();
-        let v = unsafe { spd_set_output_module_all(self.0, param.as_ptr()) };
+        let v = self.call_int(|c| unsafe { spd_set_output_module_all(c, param.as_ptr()) });
         i32_to_bool(v)
     }
 
@@ -567,29 +1128,107 @@ impl Connection {
         target_uid: u32,
     ) -> bool {
         let param = CString::new(output_module.into()).unwrap();
-        let v = unsafe { spd_set_output_module_uid(self.0, param.as_ptr(), target_uid) };
+        let v =
+            self.call_int(|c| unsafe { spd_set_output_module_uid(c, param.as_ptr(), target_uid) });
         i32_to_bool(v)
     }
 
-    pub fn send_data<S: Into<String>>(&self, data: S, wait_for_reply: bool) -> Option<String> {
+    /// Sets how many index marks speech rewinds when resumed after
+    /// `pause()`, so reading picks up a little before where it stopped.
+    pub fn set_pause_context(&self, context: u32) -> bool {
+        let ok = self
+            .command(&format!("SET SELF PAUSE_CONTEXT {}", context))
+            .is_ok();
+        if ok {
+            self.state().settings.pause_context = Some(context);
+        }
+        ok
+    }
+
+    pub fn set_pause_context_all(&self, context: u32) -> bool {
+        self.command(&format!("SET all PAUSE_CONTEXT {}", context))
+            .is_ok()
+    }
+
+    pub fn set_pause_context_uid(&self, context: u32, target_uid: u32) -> bool {
+        self.command(&format!("SET {} PAUSE_CONTEXT {}", target_uid, context))
+            .is_ok()
+    }
+
+    /// The pause context last set on this connection. SSIP has no command
+    /// to read it back, so `None` means the server's default is in use.
+    pub fn get_pause_context(&self) -> Option<u32> {
+        self.state().settings.pause_context
+    }
+
+    /// Sends raw SSIP. Fails with `Error::Timeout` if a command timeout is
+    /// set and the reply doesn't arrive in time.
+    pub fn send_data<S: Into<String>>(
+        &self,
+        data: S,
+        wait_for_reply: bool,
+    ) -> Result<Option<String>, Error> {
         let wfr: i32 = if wait_for_reply {
             SPD_WAIT_REPLY as i32
         } else {
             SPD_NO_REPLY as i32
         };
         let data = CString::new(data.into()).unwrap();
-        let rv = unsafe { spd_send_data(self.0, data.as_ptr(), wfr) };
+        let rv = self.call(
+            |c| unsafe { spd_send_data(c, data.as_ptr(), wfr) },
+            |v| v.is_null(),
+        )?;
         if rv.is_null() {
-            None
+            Ok(None)
         } else {
-            let rv = unsafe { CStr::from_ptr(rv) };
-            Some(rv.to_string_lossy().to_string())
+            let reply = unsafe { CStr::from_ptr(rv) }.to_string_lossy().to_string();
+            unsafe { libc::free(rv as *mut libc::c_void) };
+            Ok(Some(reply))
         }
     }
 
+    /// Sends one command and parses its reply, turning error replies into
+    /// `Error::Server`.
+    fn command(&self, command: &str) -> Result<Reply, Error> {
+        let raw = self
+            .send_data(format!("{}\r\n", command), true)?
+            .ok_or(Error::Disconnected)?;
+        Reply::parse(&raw)?.into_result()
+    }
+
+    /// Sends `SPEAK` and the text with the current priority, returning the
+    /// message id.
+    ///
+    /// Both go out in one call, so a reconnect can't come between them and
+    /// leave the text to be read as commands on the new connection. Only a
+    /// `SPEAK` that got no reply is retried, as nothing was queued then.
+    fn speak(&self, text: &str) -> Result<u64, Error> {
+        let speak = CString::new("SPEAK\r\n").unwrap();
+        let data = CString::new(ssip::escape_message(text)).unwrap();
+        let (speak, raw) = self.call(
+            |c| unsafe {
+                let speak = send_raw(c, &speak);
+                let accepted = match speak.as_deref().map(Reply::parse) {
+                    Some(Ok(reply)) => reply.is_ok(),
+                    _ => false,
+                };
+                let raw = if accepted { send_raw(c, &data) } else { None };
+                (speak, raw)
+            },
+            |(speak, _)| speak.is_none(),
+        )?;
+        Reply::parse(&speak.ok_or(Error::Disconnected)?)?.into_result()?;
+        let reply = Reply::parse(&raw.ok_or(Error::Disconnected)?)?.into_result()?;
+        let msg_id = reply.value()?;
+        msg_id
+            .trim()
+            .parse()
+            .map_err(|_| Error::Protocol(format!("invalid message id {:?}", msg_id)))
+    }
+
     pub fn on_begin(&self, f: Option<Box<dyn FnMut(u64, u64)>>) {
         if let Ok(mut cbs) = callbacks.lock() {
-            let cb = cbs.get_mut(&self.1);
+            let cb = cbs.get_mut(&self.client_id().0);
             if let Some(cb) = cb {
                 cb.begin = f;
             }
@@ -598,7 +1237,7 @@ impl Connection {
 
     pub fn on_end(&self, f: Option<Box<dyn FnMut(u64, u64)>>) {
         if let Ok(mut cbs) = callbacks.lock() {
-            let cb = cbs.get_mut(&self.1);
+            let cb = cbs.get_mut(&self.client_id().0);
             if let Some(cb) = cb {
                 cb.end = f;
             }
@@ -607,7 +1246,7 @@ impl Connection {
 
     pub fn on_cancel(&self, f: Option<Box<dyn FnMut(u64, u64)>>) {
         if let Ok(mut cbs) = callbacks.lock() {
-            let cb = cbs.get_mut(&self.1);
+            let cb = cbs.get_mut(&self.client_id().0);
             if let Some(cb) = cb {
                 cb.cancel = f;
             }
@@ -616,7 +1255,7 @@ impl Connection {
 
     pub fn on_pause(&self, f: Option<Box<dyn FnMut(u64, u64)>>) {
         if let Ok(mut cbs) = callbacks.lock() {
-            let cb = cbs.get_mut(&self.1);
+            let cb = cbs.get_mut(&self.client_id().0);
             if let Some(cb) = cb {
                 cb.pause = f;
             }
@@ -625,32 +1264,158 @@ impl Connection {
 
     pub fn on_resume(&self, f: Option<Box<dyn FnMut(u64, u64)>>) {
         if let Ok(mut cbs) = callbacks.lock() {
-            let cb = cbs.get_mut(&self.1);
+            let cb = cbs.get_mut(&self.client_id().0);
             if let Some(cb) = cb {
                 cb.resume = f;
             }
         }
     }
 
-    pub fn on_index_mark(&self, f: Option<Box<dyn FnMut(u64, u64, String)>>) {
+    pub fn on_index_mark(&self, f: Option<IndexMarkCallback>) {
         if let Ok(mut cbs) = callbacks.lock() {
-            let cb = cbs.get_mut(&self.1);
+            let cb = cbs.get_mut(&self.client_id().0);
             if let Some(cb) = cb {
                 cb.index_mark = f;
             }
         }
     }
 
-    pub fn client_id(&self) -> u64 {
-        self.1
+    /// Called with the new client id after the connection was reopened and
+    /// its settings restored, so the application can re-announce context.
+    pub fn on_reconnected(&self, f: Option<Box<dyn FnMut(u64)>>) {
+        if let Ok(mut cbs) = callbacks.lock() {
+            let cb = cbs.get_mut(&self.client_id().0);
+            if let Some(cb) = cb {
+                cb.reconnected = f;
+                cb.notice_sets += 1;
+            }
+        }
+    }
+
+    /// Called with the client id when the server stops answering and the
+    /// connection couldn't be reopened. It isn't called again until the
+    /// connection has been restored.
+    pub fn on_connection_lost(&self, f: Option<Box<dyn FnMut(u64)>>) {
+        if let Ok(mut cbs) = callbacks.lock() {
+            let cb = cbs.get_mut(&self.client_id().0);
+            if let Some(cb) = cb {
+                cb.connection_lost = f;
+                cb.notice_sets += 1;
+            }
+        }
     }
-}
 
-unsafe impl Send for Connection {}
+    /// Adds an observer of this connection's events, kept until it returns
+    /// false. Fails unless the connection was opened with `Mode::Threaded`,
+    /// since events never arrive otherwise.
+    fn observe(&self, observer: Observer) -> Result<(), Error> {
+        if self.state().params.mode != Mode::Threaded {
+            return Err(Error::EventsUnavailable);
+        }
+        match callbacks.lock().unwrap().get_mut(&self.client_id().0) {
+            Some(c) => {
+                c.observers.push(observer);
+                Ok(())
+            }
+            None => Err(Error::EventsUnavailable),
+        }
+    }
 
-impl Drop for Connection {
+    /// The server's id for this client. It changes when the connection is
+    /// reopened.
+    pub fn client_id(&self) -> ClientId {
+        ClientId(self.0.client_id.load(Ordering::SeqCst))
+    }
+}
+
+impl Drop for Inner {
     fn drop(&mut self) {
-        self.close();
-        callbacks.lock().unwrap().remove(&self.1);
+        let state = self.state.get_mut().unwrap();
+        if !state.closed && !state.raw.is_null() {
+            unsafe { spd_close(state.raw) };
+        }
+        callbacks
+            .lock()
+            .unwrap()
+            .remove(&self.client_id.load(Ordering::SeqCst));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// A connection whose server is gone and that has no reconnect policy,
+    /// so every call fails without reaching libspeechd. Each test passes
+    /// its own client id, since callbacks are global.
+    pub(crate) fn dead_connection(client_id: u64) -> Connection {
+        let params = OpenParams {
+            client_name: CString::new("test").unwrap(),
+            connection_name: CString::new("main").unwrap(),
+            user_name: CString::new("user").unwrap(),
+            mode: Mode::Threaded,
+            address: None,
+            autospawn: false,
+            connect_timeout: None,
+        };
+        Connection::from_raw(ptr::null_mut(), params, client_id)
+    }
+
+    #[test]
+    fn keepalive_waits_for_speak_to_finish() {
+        let connection = dead_connection(u64::MAX);
+        // say_utterance holds this between its settings and SPEAK.
+        let speaking = connection.serialize();
+        let pinger = connection.clone();
+        thread::spawn(move || pinger.ping(Duration::from_millis(10)))
+            .join()
+            .unwrap();
+        assert!(!connection.state().lost, "ping ran during SPEAK");
+        drop(speaking);
+        let pinger = connection.clone();
+        thread::spawn(move || pinger.ping(Duration::from_millis(10)))
+            .join()
+            .unwrap();
+        assert!(connection.state().lost, "ping skipped while idle");
+    }
+
+    #[test]
+    fn connection_lost_may_set_callbacks() {
+        let connection = dead_connection(u64::MAX - 80);
+        let calls = Arc::new(AtomicU64::new(0));
+        let (inner, counter) = (connection.clone(), calls.clone());
+        connection.on_connection_lost(Some(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            inner.on_begin(None);
+            let _ = inner.observe(Box::new(|_| false));
+        })));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let caller = connection.clone();
+        thread::spawn(move || {
+            caller.stop();
+            caller.state().lost = false;
+            caller.stop();
+            tx.send(()).unwrap();
+        });
+        rx.recv_timeout(Duration::from_secs(5))
+            .expect("the callback deadlocked");
+        assert_eq!(calls.load(Ordering::SeqCst), 2, "the callback was dropped");
+    }
+
+    #[test]
+    fn connection_lost_may_clear_itself() {
+        let connection = dead_connection(u64::MAX - 90);
+        let calls = Arc::new(AtomicU64::new(0));
+        let (inner, counter) = (connection.clone(), calls.clone());
+        connection.on_connection_lost(Some(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            inner.on_connection_lost(None);
+        })));
+        connection.stop();
+        connection.state().lost = false;
+        connection.stop();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
     }
 }
@@ -0,0 +1,664 @@
+//! Speaking long text as a series of smaller messages tracked as one.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use crate::{ClientId, Connection, Error, Event, Mode, Priority};
+
+/// How many chunks are queued on the server at once: the one being spoken
+/// and the next, so there is no gap between them.
+const WINDOW: usize = 2;
+
+/// How often the feeding thread checks that the client it sends to is still
+/// the one that queued its chunks.
+const POLL: Duration = Duration::from_secs(1);
+
+/// Splits `text` into chunks of at most `max` characters, breaking between
+/// paragraphs, then between sentences, then between words, and only
+/// inside a word when nothing else fits.
+fn split(text: &str, max: usize) -> Vec<String> {
+    let max = max.max(1);
+    let mut chunks = Vec::new();
+    for paragraph in paragraphs(text) {
+        let mut chunk = String::new();
+        for sentence in sentences(paragraph) {
+            let joined = if chunk.is_empty() {
+                sentence.chars().count()
+            } else {
+                chunk.chars().count() + 1 + sentence.chars().count()
+            };
+            if joined <= max {
+                if !chunk.is_empty() {
+                    chunk.push(' ');
+                }
+                chunk.push_str(sentence);
+                continue;
+            }
+            if !chunk.is_empty() {
+                chunks.push(std::mem::take(&mut chunk));
+            }
+            let mut rest = sentence;
+            while rest.chars().count() > max {
+                let (head, tail) = split_words(rest, max);
+                chunks.push(head.to_string());
+                rest = tail;
+            }
+            chunk.push_str(rest);
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+    }
+    chunks
+}
+
+/// The paragraphs of `text`, separated by blank lines.
+pub(crate) fn paragraphs(text: &str) -> impl Iterator<Item = &str> {
+    let mut paragraphs = Vec::new();
+    let mut start = 0;
+    let mut blank = false;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            blank = true;
+        } else if blank {
+            paragraphs.push(&text[start..offset]);
+            start = offset;
+            blank = false;
+        }
+        offset += line.len();
+    }
+    paragraphs.push(&text[start..]);
+    paragraphs
+        .into_iter()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+}
+
+/// The sentences of `paragraph`: runs ending in `.`, `!`, `?` or `…`, and
+/// any closing quotes or brackets, followed by whitespace.
+pub(crate) fn sentences(paragraph: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut ended = false;
+    for (i, c) in paragraph.char_indices() {
+        if c.is_whitespace() {
+            if ended {
+                sentences.push(paragraph[start..i].trim());
+                start = i;
+            }
+            ended = false;
+        } else if matches!(c, '.' | '!' | '?' | '…') {
+            ended = true;
+        } else if !matches!(c, '"' | '\'' | ')' | ']' | '”' | '’' | '»') {
+            ended = false;
+        }
+    }
+    sentences.push(paragraph[start..].trim());
+    sentences.retain(|s| !s.is_empty());
+    sentences
+}
+
+/// Splits off at most `max` characters of `text` at the last whitespace
+/// that allows it, or else after exactly `max` characters.
+fn split_words(text: &str, max: usize) -> (&str, &str) {
+    let end = text.char_indices().nth(max).map_or(text.len(), |(i, _)| i);
+    let at = if text[end..].starts_with(char::is_whitespace) {
+        end
+    } else {
+        text[..end]
+            .rfind(char::is_whitespace)
+            .filter(|&i| i > 0)
+            .unwrap_or(end)
+    };
+    (text[..at].trim_end(), text[at..].trim_start())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChunkState {
+    Unsent,
+    Queued,
+    Speaking,
+    Paused,
+    Done,
+    Cancelled,
+}
+
+/// What the feeding thread does next.
+#[derive(Debug)]
+enum Action {
+    Send(usize, String),
+    Cancel,
+    Pause,
+    Resume,
+}
+
+#[derive(Debug)]
+struct Group {
+    chunks: Vec<String>,
+    states: Vec<ChunkState>,
+    /// Message ids to chunk indices, filled in as the chunks are sent.
+    ids: HashMap<u64, usize>,
+    /// Events that arrived before the id of their message was known.
+    early: Vec<Event>,
+    sending: bool,
+    /// The chunk to send next.
+    next: usize,
+    cancel: bool,
+    pause: bool,
+    /// Whether the feeding thread paused one of the chunks on the server.
+    paused: bool,
+    /// The first error the feeding thread ran into.
+    error: Option<Error>,
+}
+
+impl Group {
+    fn update(&mut self, event: &Event) {
+        let msg_id = event.msg_id();
+        if self.sending && !self.ids.contains_key(&msg_id) {
+            self.early.push(event.clone());
+            return;
+        }
+        if let Some(&index) = self.ids.get(&msg_id) {
+            let state = &mut self.states[index];
+            *state = match (event, *state) {
+                (_, ChunkState::Done) | (_, ChunkState::Cancelled) => *state,
+                (Event::Begin { .. }, _) | (Event::Resume { .. }, _) => ChunkState::Speaking,
+                (Event::Pause { .. }, _) => ChunkState::Paused,
+                (Event::End { .. }, _) => ChunkState::Done,
+                (Event::Cancel { .. }, _) => ChunkState::Cancelled,
+                _ => *state,
+            };
+        }
+    }
+
+    fn finished(&self) -> bool {
+        !self.sending && self.states.iter().all(|s| is_over(*s))
+    }
+
+    fn count(&self, states: &[ChunkState]) -> usize {
+        self.states.iter().filter(|s| states.contains(s)).count()
+    }
+
+    /// Marks the chunks not sent yet as cancelled, since they never will be.
+    fn drop_unsent(&mut self) {
+        for state in &mut self.states[self.next..] {
+            *state = ChunkState::Cancelled;
+        }
+        self.next = self.chunks.len();
+    }
+
+    /// Marks every chunk not over as cancelled.
+    fn abandon(&mut self) {
+        for state in self.states.iter_mut().filter(|s| !is_over(**s)) {
+            *state = ChunkState::Cancelled;
+        }
+        self.next = self.chunks.len();
+    }
+
+    /// What to send to the server for the owner's requests, if anything.
+    /// Cancelling and pausing the chunks' client covers those queued as
+    /// well as the one being spoken.
+    fn action(&mut self) -> Option<Action> {
+        let queued = self.count(&[ChunkState::Queued, ChunkState::Speaking, ChunkState::Paused]);
+        if self.cancel {
+            self.drop_unsent();
+            return if queued > 0 {
+                Some(Action::Cancel)
+            } else {
+                None
+            };
+        }
+        if self.pause && !self.paused && queued > 0 {
+            return Some(Action::Pause);
+        }
+        if !self.pause && self.paused {
+            return Some(Action::Resume);
+        }
+        if !self.pause && self.next < self.chunks.len() && queued < WINDOW {
+            let index = self.next;
+            return Some(Action::Send(index, self.chunks[index].clone()));
+        }
+        None
+    }
+}
+
+fn is_over(state: ChunkState) -> bool {
+    matches!(state, ChunkState::Done | ChunkState::Cancelled)
+}
+
+#[derive(Debug)]
+struct Shared {
+    group: Mutex<Group>,
+    changed: Condvar,
+}
+
+/// How far a `LongMessage` has got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// Chunks spoken to the end.
+    pub chunks_done: usize,
+    pub chunks: usize,
+    /// Characters in the chunks spoken to the end.
+    pub chars_done: usize,
+    pub chars: usize,
+    /// The index of the chunk being spoken, if any.
+    pub current: Option<usize>,
+}
+
+impl Progress {
+    /// The share of characters spoken, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        if self.chars == 0 {
+            1.0
+        } else {
+            self.chars_done as f64 / self.chars as f64
+        }
+    }
+}
+
+/// Text sent by `say_long` as several messages, tracked and controlled as
+/// one.
+///
+/// The chunks are spoken by a client of their own, opened with the names
+/// and settings of the connection `say_long` was called on. That connection
+/// cancels, pauses and resumes them by the client's id, leaving its other
+/// messages alone, and its callbacks don't see the chunks.
+///
+/// A thread sends the chunks a couple at a time as the earlier ones end,
+/// following their events, so the connection must be opened with
+/// `Mode::Threaded`. Messages from elsewhere may be spoken between chunks.
+#[derive(Debug)]
+pub struct LongMessage {
+    shared: Arc<Shared>,
+}
+
+impl LongMessage {
+    fn group(&self) -> std::sync::MutexGuard<'_, Group> {
+        self.shared.group.lock().unwrap()
+    }
+
+    /// Records a request for the feeding thread.
+    fn request(&self, f: impl FnOnce(&mut Group)) {
+        f(&mut self.group());
+        self.shared.changed.notify_all();
+    }
+
+    pub fn progress(&self) -> Progress {
+        let group = self.group();
+        let chars = |index: usize| group.chunks[index].chars().count();
+        let done: Vec<usize> = (0..group.chunks.len())
+            .filter(|&i| group.states[i] == ChunkState::Done)
+            .collect();
+        Progress {
+            chunks_done: done.len(),
+            chunks: group.chunks.len(),
+            chars_done: done.iter().map(|&i| chars(i)).sum(),
+            chars: (0..group.chunks.len()).map(chars).sum(),
+            current: group
+                .states
+                .iter()
+                .position(|s| matches!(s, ChunkState::Speaking | ChunkState::Paused)),
+        }
+    }
+
+    /// Whether every chunk was spoken or cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.group().finished()
+    }
+
+    /// The message ids of the chunks sent so far, in order.
+    pub fn message_ids(&self) -> Vec<u64> {
+        let group = self.group();
+        let mut ids: Vec<(u64, usize)> = group.ids.iter().map(|(id, i)| (*id, *i)).collect();
+        ids.sort_by_key(|(_, i)| *i);
+        ids.into_iter().map(|(id, _)| id).collect()
+    }
+
+    pub fn chunks(&self) -> Vec<String> {
+        self.group().chunks.clone()
+    }
+
+    /// The text from the first chunk not spoken to the end, for speaking
+    /// again after cancelling.
+    pub fn remaining(&self) -> String {
+        let group = self.group();
+        let first = group
+            .states
+            .iter()
+            .position(|s| *s != ChunkState::Done)
+            .unwrap_or(group.chunks.len());
+        group.chunks[first..].join("\n\n")
+    }
+
+    /// The first error sending a chunk or a request ran into, which stops
+    /// the chunks not sent yet. Taking it clears it.
+    pub fn last_error(&self) -> Option<Error> {
+        self.group().error.take()
+    }
+
+    /// Stops the chunk being spoken and throws away the rest.
+    pub fn cancel(&self) {
+        self.request(|g| g.cancel = true);
+    }
+
+    /// Holds the chunks back until `resume`, pausing the one being spoken.
+    pub fn pause(&self) {
+        self.request(|g| g.pause = true);
+    }
+
+    pub fn resume(&self) {
+        self.request(|g| g.pause = false);
+    }
+}
+
+impl Connection {
+    /// Speaks `text` as a series of messages of at most `max_chunk`
+    /// characters, split between paragraphs and sentences where possible.
+    /// The first chunk is sent before this returns, and the rest as the
+    /// server gets through them. The text is sent as it is, so it must not
+    /// be SSML.
+    ///
+    /// Fails with `Error::EventsUnavailable` unless the connection was
+    /// opened with `Mode::Threaded`, and with `Error::Disconnected` if the
+    /// client for the chunks can't be opened.
+    pub fn say_long<S: Into<String>>(
+        &self,
+        priority: Priority,
+        text: S,
+        max_chunk: usize,
+    ) -> Result<LongMessage, Error> {
+        if self.state().params.mode != Mode::Threaded {
+            return Err(Error::EventsUnavailable);
+        }
+        let speaker = self.open_sibling()?;
+        let chunks = split(&text.into(), max_chunk);
+        let shared = Arc::new(Shared {
+            group: Mutex::new(Group {
+                states: vec![ChunkState::Unsent; chunks.len()],
+                chunks,
+                ids: HashMap::new(),
+                early: Vec::new(),
+                sending: false,
+                next: 0,
+                cancel: false,
+                pause: false,
+                paused: false,
+                error: None,
+            }),
+            changed: Condvar::new(),
+        });
+        let weak: Weak<Shared> = Arc::downgrade(&shared);
+        speaker.observe(Box::new(move |event| {
+            let shared = match weak.upgrade() {
+                Some(shared) => shared,
+                None => return false,
+            };
+            let mut group = shared.group.lock().unwrap();
+            group.update(event);
+            shared.changed.notify_all();
+            !group.finished()
+        }))?;
+
+        let first = shared.group.lock().unwrap().action();
+        if let Some(Action::Send(index, chunk)) = first {
+            send(&speaker, &shared, priority, index, &chunk)?;
+        }
+        let owner = self.clone();
+        let feeder = shared.clone();
+        thread::spawn(move || feed(owner, speaker, feeder, priority));
+        Ok(LongMessage { shared })
+    }
+}
+
+/// Sends the chunk at `index`, recording its message id. On failure the
+/// chunks not sent yet are dropped.
+fn send(
+    connection: &Connection,
+    shared: &Shared,
+    priority: Priority,
+    index: usize,
+    chunk: &str,
+) -> Result<(), Error> {
+    let _op = connection.serialize();
+    shared.group.lock().unwrap().sending = true;
+    let sent = connection
+        .command(&format!("SET SELF PRIORITY {}", priority.as_ssip()))
+        .and_then(|_| connection.speak(chunk));
+    let mut group = shared.group.lock().unwrap();
+    group.sending = false;
+    let early = std::mem::take(&mut group.early);
+    let sent = sent.map(|msg_id| {
+        group.ids.insert(msg_id, index);
+        group.states[index] = ChunkState::Queued;
+        group.next = index + 1;
+    });
+    if sent.is_err() {
+        group.drop_unsent();
+    }
+    for event in &early {
+        group.update(event);
+    }
+    drop(group);
+    shared.changed.notify_all();
+    sent
+}
+
+/// Carries out the requests of a `LongMessage` through `owner` and sends
+/// its chunks on `speaker` until every one is over. Gives up if either
+/// connection closes or `speaker` is reopened, since the server forgets the
+/// queued chunks then.
+fn feed(owner: Connection, speaker: Connection, shared: Arc<Shared>, priority: Priority) {
+    let target: ClientId = speaker.client_id();
+    loop {
+        let mut group = shared.group.lock().unwrap();
+        let action = loop {
+            if group.finished() {
+                return;
+            }
+            if let Some(action) = group.action() {
+                break action;
+            }
+            let (guard, wait) = shared.changed.wait_timeout(group, POLL).unwrap();
+            group = guard;
+            if wait.timed_out() {
+                // The state lock is held while libspeechd waits for replies
+                // that come through the observer, so it can't be taken
+                // with the group locked.
+                drop(group);
+                let gone =
+                    speaker.client_id() != target || speaker.state().closed || owner.state().closed;
+                group = shared.group.lock().unwrap();
+                if gone {
+                    group.abandon();
+                    return;
+                }
+            }
+        };
+        drop(group);
+
+        let result = match &action {
+            Action::Send(index, chunk) => send(&speaker, &shared, priority, *index, chunk),
+            Action::Cancel => owner.command(&format!("CANCEL {}", target)).map(|_| ()),
+            Action::Pause => owner.command(&format!("PAUSE {}", target)).map(|_| ()),
+            Action::Resume => owner.command(&format!("RESUME {}", target)).map(|_| ()),
+        };
+        let mut group = shared.group.lock().unwrap();
+        match action {
+            // The client's queued chunks go with the one being spoken.
+            Action::Cancel => group.abandon(),
+            // Not retried if they failed, so the thread doesn't spin.
+            Action::Pause => group.paused = true,
+            Action::Resume => group.paused = false,
+            Action::Send(..) => (),
+        }
+        if let Err(e) = result {
+            group.error.get_or_insert(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+
+    fn group(chunks: &[&str]) -> Group {
+        Group {
+            chunks: chunks.iter().map(|c| c.to_string()).collect(),
+            states: vec![ChunkState::Unsent; chunks.len()],
+            ids: HashMap::new(),
+            early: Vec::new(),
+            sending: false,
+            next: 0,
+            cancel: false,
+            pause: false,
+            paused: false,
+            error: None,
+        }
+    }
+
+    /// Records the chunk at `index` as sent with message id `msg_id`.
+    fn sent(group: &mut Group, index: usize, msg_id: u64) {
+        assert!(matches!(group.action(), Some(Action::Send(i, _)) if i == index));
+        group.ids.insert(msg_id, index);
+        group.states[index] = ChunkState::Queued;
+        group.next = index + 1;
+    }
+
+    fn begin(msg_id: u64) -> Event {
+        Event::Begin {
+            msg_id,
+            client_id: 0,
+        }
+    }
+
+    #[test]
+    fn paragraphs_are_separated_by_blank_lines() {
+        let text = "\n\nOne.\n\n  \nTwo\nstill two.\n\n\nThree.\n";
+        let found: Vec<&str> = paragraphs(text).collect();
+        assert_eq!(found, ["One.", "Two\nstill two.", "Three."]);
+        assert_eq!(paragraphs(" \n\n ").count(), 0);
+    }
+
+    #[test]
+    fn sentences_end_after_closing_quotes() {
+        assert_eq!(
+            sentences("Wait… What? “Yes.” (Sure!) e.g.x Done"),
+            ["Wait…", "What?", "“Yes.”", "(Sure!)", "e.g.x Done"]
+        );
+        assert!(sentences("").is_empty());
+    }
+
+    #[test]
+    fn split_words_keeps_to_char_boundaries() {
+        assert_eq!(split_words("héllo wörld", 7), ("héllo", "wörld"));
+        assert_eq!(split_words("héllo wörld", 5), ("héllo", "wörld"));
+        assert_eq!(split_words("ééééé", 2), ("éé", "ééé"));
+        assert_eq!(split_words("abcdefgh ij", 3), ("abc", "defgh ij"));
+    }
+
+    #[test]
+    fn split_packs_sentences_within_paragraphs() {
+        assert_eq!(
+            split("One. Two. Three.\n\nFour.", 10),
+            ["One. Two.", "Three.", "Four."]
+        );
+        assert_eq!(split("One.\n\nTwo.", 100), ["One.", "Two."]);
+        assert!(split("", 10).is_empty());
+    }
+
+    #[test]
+    fn split_breaks_long_words() {
+        assert_eq!(split("abcdefgh", 3), ["abc", "def", "gh"]);
+        assert_eq!(split("ééééé…", 2), ["éé", "éé", "é…"]);
+        assert_eq!(split("ab c", 0), ["a", "b", "c"]);
+        assert_eq!(split("ab c", 1), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn only_a_window_of_chunks_is_queued() {
+        let mut group = group(&["a", "b", "c"]);
+        sent(&mut group, 0, 10);
+        sent(&mut group, 1, 11);
+        assert!(group.action().is_none());
+        group.update(&Event::End {
+            msg_id: 10,
+            client_id: 0,
+        });
+        sent(&mut group, 2, 12);
+    }
+
+    #[test]
+    fn cancel_reaches_queued_chunks() {
+        let mut unsent = group(&["a", "b", "c"]);
+        unsent.cancel = true;
+        // Nothing was sent, so there is nothing to cancel on the server.
+        assert!(unsent.action().is_none());
+        assert!(unsent.finished());
+
+        let mut group = group(&["a", "b", "c"]);
+        sent(&mut group, 0, 10);
+        group.cancel = true;
+        // The chunk is still behind another message.
+        assert!(matches!(group.action(), Some(Action::Cancel)));
+        assert_eq!(group.states[1..], [ChunkState::Cancelled; 2]);
+    }
+
+    #[test]
+    fn pause_holds_queued_chunks() {
+        let mut group = group(&["a", "b", "c"]);
+        group.pause = true;
+        assert!(group.action().is_none());
+        group.pause = false;
+        sent(&mut group, 0, 10);
+        group.pause = true;
+        assert!(matches!(group.action(), Some(Action::Pause)));
+        group.update(&begin(10));
+        group.paused = true;
+        assert!(group.action().is_none());
+        group.pause = false;
+        assert!(matches!(group.action(), Some(Action::Resume)));
+    }
+
+    #[test]
+    fn remaining_after_cancel() {
+        let mut group = group(&["a", "b", "c"]);
+        sent(&mut group, 0, 10);
+        sent(&mut group, 1, 11);
+        group.update(&begin(10));
+        group.update(&Event::End {
+            msg_id: 10,
+            client_id: 0,
+        });
+        group.update(&begin(11));
+        group.update(&Event::Cancel {
+            msg_id: 11,
+            client_id: 0,
+        });
+        let message = LongMessage {
+            shared: Arc::new(Shared {
+                group: Mutex::new(group),
+                changed: Condvar::new(),
+            }),
+        };
+        message.cancel();
+        assert!(message.group().action().is_none());
+        assert!(message.is_finished());
+        assert_eq!(message.remaining(), "b\n\nc");
+        assert_eq!(message.progress().chunks_done, 1);
+        assert_eq!(message.message_ids(), [10, 11]);
+    }
+
+    #[test]
+    fn say_long_needs_events() {
+        let connection = crate::tests::dead_connection(u64::MAX - 40);
+        connection.state().params.mode = Mode::Single;
+        assert!(matches!(
+            connection.say_long(Priority::Text, "Hello.", 10),
+            Err(Error::EventsUnavailable)
+        ));
+    }
+}
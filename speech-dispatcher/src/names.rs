@@ -0,0 +1,184 @@
+//! The SSIP spellings of the public enums, and parsing them back.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{CapitalLetters, DataMode, Mode, Notification, Priority, Punctuation, VoiceType};
+
+/// A string that isn't the name of any variant of an enum.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnumParseError {
+    kind: &'static str,
+    value: String,
+    expected: Vec<&'static str>,
+}
+
+impl EnumParseError {
+    /// What was being parsed, such as `"voice type"`.
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    /// The names that would have been accepted.
+    pub fn expected(&self) -> &[&'static str] {
+        &self.expected
+    }
+}
+
+impl fmt::Display for EnumParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid {} {:?}, expected one of: {}",
+            self.kind,
+            self.value,
+            self.expected.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for EnumParseError {}
+
+/// Gives an enum its SSIP spelling through `as_ssip` and `Display`, and
+/// parses it back, ignoring case, through `FromStr`.
+macro_rules! ssip_names {
+    ($ty:ident, $kind:literal, { $($(#[$attr:meta])* $variant:ident => $name:literal,)+ }) => {
+        impl $ty {
+            pub(crate) fn as_ssip(self) -> &'static str {
+                match self {
+                    $($(#[$attr])* $ty::$variant => $name,)+
+                }
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.as_ssip())
+            }
+        }
+
+        impl FromStr for $ty {
+            type Err = EnumParseError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $(
+                    $(#[$attr])*
+                    {
+                        if s.eq_ignore_ascii_case($name) {
+                            return Ok($ty::$variant);
+                        }
+                    }
+                )+
+                Err(EnumParseError {
+                    kind: $kind,
+                    value: s.to_string(),
+                    expected: vec![$($(#[$attr])* $name,)+],
+                })
+            }
+        }
+    };
+}
+
+ssip_names!(Mode, "connection mode", {
+    Single => "single",
+    Threaded => "threaded",
+});
+
+ssip_names!(Priority, "priority", {
+    Important => "important",
+    Message => "message",
+    Text => "text",
+    Notification => "notification",
+    Progress => "progress",
+});
+
+ssip_names!(VoiceType, "voice type", {
+    Male1 => "male1",
+    Male2 => "male2",
+    Male3 => "male3",
+    Female1 => "female1",
+    Female2 => "female2",
+    Female3 => "female3",
+    ChildMale => "child_male",
+    ChildFemale => "child_female",
+});
+
+ssip_names!(DataMode, "data mode", {
+    Text => "text",
+    SSML => "ssml",
+});
+
+ssip_names!(Notification, "notification", {
+    Begin => "begin",
+    End => "end",
+    IndexMarks => "index_marks",
+    Cancel => "cancel",
+    Pause => "pause",
+    Resume => "resume",
+    All => "all",
+});
+
+ssip_names!(Punctuation, "punctuation mode", {
+    All => "all",
+    None => "none",
+    Some => "some",
+    #[cfg(feature = "0_10")]
+    Most => "most",
+});
+
+ssip_names!(CapitalLetters, "capital letters mode", {
+    None => "none",
+    Spell => "spell",
+    Icon => "icon",
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for v in &[
+            VoiceType::Male1,
+            VoiceType::Female3,
+            VoiceType::ChildMale,
+            VoiceType::ChildFemale,
+        ] {
+            assert_eq!(v.to_string().parse::<VoiceType>().unwrap(), *v);
+        }
+        for p in &[Priority::Important, Priority::Progress] {
+            assert_eq!(p.to_string().parse::<Priority>().unwrap(), *p);
+        }
+        assert_eq!(Notification::IndexMarks.to_string(), "index_marks");
+        assert_eq!(DataMode::SSML.to_string(), "ssml");
+    }
+
+    #[test]
+    fn parsing_ignores_case() {
+        assert_eq!("CHILD_Female".parse(), Ok(VoiceType::ChildFemale));
+        assert_eq!("Threaded".parse(), Ok(Mode::Threaded));
+        assert_eq!("SPELL".parse(), Ok(CapitalLetters::Spell));
+    }
+
+    #[test]
+    fn errors_list_the_expected_names() {
+        let err = "loud".parse::<CapitalLetters>().unwrap_err();
+        assert_eq!(err.kind(), "capital letters mode");
+        assert_eq!(err.expected(), ["none", "spell", "icon"]);
+        assert_eq!(
+            err.to_string(),
+            "invalid capital letters mode \"loud\", expected one of: none, spell, icon"
+        );
+        assert!("child male".parse::<VoiceType>().is_err());
+        assert!("".parse::<Priority>().is_err());
+    }
+
+    #[test]
+    fn punctuation_most_needs_0_10() {
+        let most = "most".parse::<Punctuation>();
+        #[cfg(feature = "0_10")]
+        assert_eq!(most, Ok(Punctuation::Most));
+        #[cfg(not(feature = "0_10"))]
+        assert_eq!(most.unwrap_err().expected(), ["all", "none", "some"]);
+    }
+}
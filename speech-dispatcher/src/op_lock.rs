@@ -0,0 +1,56 @@
+//! Serializes sequences of commands that must not interleave with commands
+//! from other threads, such as changing a setting, speaking and changing it
+//! back.
+
+use std::marker::PhantomData;
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, ThreadId};
+
+/// A lock the owning thread may take again, so a sequence can be built
+/// from methods that take it themselves.
+#[derive(Debug, Default)]
+pub(crate) struct OpLock {
+    owner: Mutex<(Option<ThreadId>, usize)>,
+    released: Condvar,
+}
+
+/// Belongs to the thread that took the lock, so it can't be sent to
+/// another one.
+#[derive(Debug)]
+pub(crate) struct OpGuard<'a>(&'a OpLock, PhantomData<*const ()>);
+
+impl OpLock {
+    pub fn lock(&self) -> OpGuard<'_> {
+        let me = thread::current().id();
+        let mut owner = self.owner.lock().unwrap();
+        while owner.0.is_some() && owner.0 != Some(me) {
+            owner = self.released.wait(owner).unwrap();
+        }
+        owner.0 = Some(me);
+        owner.1 += 1;
+        OpGuard(self, PhantomData)
+    }
+
+    /// Like `lock`, but gives up instead of waiting for another thread.
+    pub fn try_lock(&self) -> Option<OpGuard<'_>> {
+        let me = thread::current().id();
+        let mut owner = self.owner.lock().unwrap();
+        if owner.0.is_some() && owner.0 != Some(me) {
+            return None;
+        }
+        owner.0 = Some(me);
+        owner.1 += 1;
+        Some(OpGuard(self, PhantomData))
+    }
+}
+
+impl Drop for OpGuard<'_> {
+    fn drop(&mut self) {
+        let mut owner = self.0.owner.lock().unwrap();
+        owner.1 -= 1;
+        if owner.1 == 0 {
+            owner.0 = None;
+            self.0.released.notify_one();
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Named sets of voice settings, such as a preset for proofreading.
+
+#[cfg(feature = "serde")]
+use std::env;
+#[cfg(feature = "serde")]
+use std::fs;
+#[cfg(feature = "serde")]
+use std::io;
+#[cfg(feature = "serde")]
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::history::parse_first_number;
+use crate::ssip::on_off;
+use crate::{
+    CapitalLetters, Connection, DataMode, Error, LanguageTag, Punctuation, Settings, Supervisor,
+    Target, VoiceType,
+};
+
+/// Values for any of the settings the `Connection` setters expose. Settings
+/// left out are not changed when the profile is applied.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct Profile {
+    pub output_module: Option<String>,
+    pub language: Option<LanguageTag>,
+    pub voice_type: Option<VoiceType>,
+    /// Takes precedence over `voice_type`.
+    pub synthesis_voice: Option<String>,
+    pub rate: Option<i32>,
+    pub pitch: Option<i32>,
+    /// Needs speech-dispatcher 0.11 on the server.
+    pub pitch_range: Option<i32>,
+    pub volume: Option<i32>,
+    pub punctuation: Option<Punctuation>,
+    pub capital_letters: Option<CapitalLetters>,
+    pub spelling: Option<bool>,
+    /// Only applies to this connection.
+    pub data_mode: Option<DataMode>,
+    pub pause_context: Option<u32>,
+}
+
+impl Profile {
+    /// The names of the built-in profiles.
+    pub const BUILTIN: &'static [&'static str] = &["fast-reading", "proofreading", "notifications"];
+
+    /// A built-in preset: `fast-reading` speeds speech up and stays quiet
+    /// about punctuation, `proofreading` reads every punctuation mark and
+    /// spells capitals, and `notifications` is quick and a little higher
+    /// pitched so it stands out from other speech.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let profile = match name {
+            "fast-reading" => Profile {
+                rate: Some(60),
+                punctuation: Some(Punctuation::None),
+                ..Default::default()
+            },
+            "proofreading" => Profile {
+                punctuation: Some(Punctuation::All),
+                capital_letters: Some(CapitalLetters::Spell),
+                ..Default::default()
+            },
+            "notifications" => Profile {
+                rate: Some(30),
+                pitch: Some(20),
+                punctuation: Some(Punctuation::None),
+                ..Default::default()
+            },
+            _ => return None,
+        };
+        Some(profile)
+    }
+
+    /// The settings this connection changed, as a profile.
+    pub fn from_settings(settings: &Settings) -> Self {
+        Profile {
+            output_module: settings.output_module.clone(),
+            language: settings.language.clone(),
+            voice_type: settings.voice_type,
+            synthesis_voice: settings.synthesis_voice.clone(),
+            rate: settings.rate,
+            pitch: settings.pitch,
+            pitch_range: settings.pitch_range,
+            volume: settings.volume,
+            punctuation: settings.punctuation,
+            capital_letters: settings.capital_letters,
+            spelling: settings.spelling,
+            data_mode: settings.data_mode,
+            pause_context: settings.pause_context,
+        }
+    }
+
+    /// The `SET` commands for `target` in the order they must be sent: the
+    /// output module and language first, since changing them resets the
+    /// voice. Each comes with the name of the setting, for `record`.
+    fn commands(&self, target: &str) -> Vec<(&'static str, String)> {
+        let mut commands = Vec::new();
+        let mut set = |name: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                commands.push((name, format!("SET {} {} {}", target, name, value)));
+            }
+        };
+        set("OUTPUT_MODULE", self.output_module.clone());
+        set("LANGUAGE", self.language.as_ref().map(|v| v.to_string()));
+        match &self.synthesis_voice {
+            Some(voice) => set("SYNTHESIS_VOICE", Some(voice.clone())),
+            None => set("VOICE_TYPE", self.voice_type.map(|v| v.as_ssip().into())),
+        }
+        set("RATE", self.rate.map(|v| v.to_string()));
+        set("PITCH", self.pitch.map(|v| v.to_string()));
+        set("PITCH_RANGE", self.pitch_range.map(|v| v.to_string()));
+        set("VOLUME", self.volume.map(|v| v.to_string()));
+        set("PUNCTUATION", self.punctuation.map(|v| v.as_ssip().into()));
+        set(
+            "CAP_LET_RECOGN",
+            self.capital_letters.map(|v| v.as_ssip().into()),
+        );
+        set("SPELLING", self.spelling.map(|v| on_off(v).into()));
+        if target == "self" {
+            set("SSML_MODE", self.data_mode.map(|v| v.ssml_mode().into()));
+        }
+        set("PAUSE_CONTEXT", self.pause_context.map(|v| v.to_string()));
+        commands
+    }
+
+    /// Records in `s` the setting a command from `commands` changed.
+    fn record(&self, name: &str, s: &mut Settings) {
+        match name {
+            "OUTPUT_MODULE" => s.output_module = self.output_module.clone(),
+            "LANGUAGE" => s.language = self.language.clone(),
+            "SYNTHESIS_VOICE" => {
+                s.synthesis_voice = self.synthesis_voice.clone();
+                s.voice_type = None;
+            }
+            "VOICE_TYPE" => {
+                s.voice_type = self.voice_type;
+                s.synthesis_voice = None;
+            }
+            "RATE" => s.rate = self.rate,
+            "PITCH" => s.pitch = self.pitch,
+            "PITCH_RANGE" => s.pitch_range = self.pitch_range,
+            "VOLUME" => s.volume = self.volume,
+            "PUNCTUATION" => s.punctuation = self.punctuation,
+            "CAP_LET_RECOGN" => s.capital_letters = self.capital_letters,
+            "SPELLING" => s.spelling = self.spelling,
+            "SSML_MODE" => s.data_mode = self.data_mode,
+            "PAUSE_CONTEXT" => s.pause_context = self.pause_context,
+            _ => unreachable!("no profile setting is sent as {}", name),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Profile {
+    pub fn from_toml(s: &str) -> Result<Self, Error> {
+        toml::from_str(s).map_err(|e| Error::InvalidProfile(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(self).map_err(|e| Error::InvalidProfile(e.to_string()))
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, Error> {
+        serde_json::from_str(s).map_err(|e| Error::InvalidProfile(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::InvalidProfile(e.to_string()))
+    }
+
+    /// Reads a profile from a `.toml` or `.json` file.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            _ => Self::from_toml(&contents),
+        }
+    }
+
+    /// Loads `name.toml` or `name.json` from the first `profiles` directory
+    /// that has it, the user's one first, or else the built-in profile of
+    /// that name.
+    pub fn load(name: &str) -> Result<Self, Error> {
+        if name.is_empty() || name.contains('/') || name.starts_with('.') {
+            return Err(Error::InvalidProfile(format!("invalid name {:?}", name)));
+        }
+        for dir in profile_dirs() {
+            for extension in &["toml", "json"] {
+                let path = dir.join(format!("{}.{}", name, extension));
+                match Self::load_file(&path) {
+                    Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => (),
+                    r => return r,
+                }
+            }
+        }
+        Self::builtin(name)
+            .ok_or_else(|| Error::InvalidProfile(format!("no profile named {:?}", name)))
+    }
+
+    /// The names of the profiles `load` can find.
+    pub fn available() -> Vec<String> {
+        let mut names: Vec<String> = Self::BUILTIN.iter().map(|name| name.to_string()).collect();
+        for dir in profile_dirs() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let is_profile = matches!(
+                    path.extension().and_then(|e| e.to_str()),
+                    Some("toml") | Some("json")
+                );
+                if let (true, Some(name)) = (is_profile, path.file_stem().and_then(|s| s.to_str()))
+                {
+                    if !names.iter().any(|n| n == name) {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+        names
+    }
+}
+
+/// `speech-dispatcher/profiles` under the XDG configuration directories, the
+/// user's first.
+#[cfg(feature = "serde")]
+fn profile_dirs() -> Vec<PathBuf> {
+    let mut config_dirs = Vec::new();
+    let user_config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    config_dirs.extend(user_config);
+    match env::var_os("XDG_CONFIG_DIRS") {
+        Some(dirs) if !dirs.is_empty() => config_dirs.extend(env::split_paths(&dirs)),
+        _ => config_dirs.push(PathBuf::from("/etc/xdg")),
+    }
+    config_dirs
+        .into_iter()
+        .map(|dir| dir.join("speech-dispatcher/profiles"))
+        .collect()
+}
+
+impl Connection {
+    /// Applies every setting the profile has, stopping at the first the
+    /// server refuses. Each setting is recorded as soon as it is applied,
+    /// so those that went through are kept across reconnects even if a
+    /// later one fails.
+    pub fn apply_profile(&self, profile: &Profile) -> Result<(), Error> {
+        let _op = self.serialize();
+        for (name, command) in profile.commands("self") {
+            self.command(&command)?;
+            profile.record(name, &mut self.state().settings);
+        }
+        Ok(())
+    }
+
+    /// The current settings as a profile. Those this connection never
+    /// changed are asked of the server where SSIP allows it.
+    pub fn capture_profile(&self) -> Result<Profile, Error> {
+        let _op = self.serialize();
+        let mut profile = Profile::from_settings(&self.settings());
+        let get = |name: &str| -> Result<String, Error> {
+            Ok(self.command(&format!("GET {}", name))?.value()?.to_string())
+        };
+        let get_number = |name: &str| -> Result<i32, Error> {
+            parse_first_number(
+                &name.to_lowercase(),
+                &self.command(&format!("GET {}", name))?,
+            )
+        };
+        if profile.output_module.is_none() {
+            profile.output_module = Some(get("OUTPUT_MODULE")?);
+        }
+        if profile.language.is_none() {
+            profile.language = get("LANGUAGE")?.parse().ok();
+        }
+        if profile.synthesis_voice.is_none() && profile.voice_type.is_none() {
+            profile.voice_type = get("VOICE_TYPE")?.parse().ok();
+        }
+        if profile.rate.is_none() {
+            profile.rate = Some(get_number("RATE")?);
+        }
+        if profile.pitch.is_none() {
+            profile.pitch = Some(get_number("PITCH")?);
+        }
+        if profile.volume.is_none() {
+            profile.volume = Some(get_number("VOLUME")?);
+        }
+        Ok(profile)
+    }
+}
+
+impl Supervisor {
+    /// Applies a profile to another client, or to all of them. The data
+    /// mode is left alone, since it can only be set by a client itself.
+    pub fn apply_profile(&self, target: Target, profile: &Profile) -> Result<(), Error> {
+        let connection = self.connection();
+        let _op = connection.serialize();
+        for (_, command) in profile.commands(&target.to_string()) {
+            connection.command(&command)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_command_records_its_setting() {
+        let profile = Profile {
+            output_module: Some("espeak-ng".to_string()),
+            language: Some("en-GB".parse().unwrap()),
+            voice_type: Some(VoiceType::Female1),
+            synthesis_voice: Some("Alice".to_string()),
+            rate: Some(10),
+            pitch: Some(-5),
+            pitch_range: Some(20),
+            volume: Some(80),
+            punctuation: Some(Punctuation::Some),
+            capital_letters: Some(CapitalLetters::Icon),
+            spelling: Some(true),
+            data_mode: Some(DataMode::SSML),
+            pause_context: Some(3),
+        };
+        let mut settings = Settings::default();
+        for (name, _) in profile.commands("self") {
+            profile.record(name, &mut settings);
+        }
+        // Only the synthesis voice is sent when both are set.
+        let expected = Profile {
+            voice_type: None,
+            ..profile
+        };
+        assert_eq!(Profile::from_settings(&settings), expected);
+    }
+
+    #[test]
+    fn failed_command_records_nothing() {
+        let connection = crate::tests::dead_connection(u64::MAX - 30);
+        let profile = Profile::builtin("proofreading").unwrap();
+        assert!(connection.apply_profile(&profile).is_err());
+        assert_eq!(
+            Profile::from_settings(&connection.settings()),
+            Profile::default()
+        );
+    }
+}
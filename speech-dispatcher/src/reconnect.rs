@@ -0,0 +1,406 @@
+//! Detecting a dropped server connection and reopening it.
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::net::Ipv6Addr;
+use std::os::raw::c_char;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use speech_dispatcher_sys::*;
+
+use crate::{setup, Address, Error, Feature, Mode, Notice, State};
+
+/// How a `Connection` reopens itself after the server went away.
+///
+/// Attempts are made lazily, by the next call that finds the connection
+/// dead, so nothing blocks while waiting. After each failed attempt the
+/// delay before the next one doubles, up to `max_delay`.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Give up after this many failed attempts in a row. `None` retries
+    /// forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    fn delay(&self, failures: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(failures.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |d| d.min(self.max_delay))
+    }
+}
+
+/// Everything needed to open the connection again.
+#[derive(Clone, Debug)]
+pub(crate) struct OpenParams {
+    pub client_name: CString,
+    pub connection_name: CString,
+    pub user_name: CString,
+    pub mode: Mode,
+    pub address: Option<Destination>,
+    pub autospawn: bool,
+    /// How long libspeechd may take to connect.
+    pub connect_timeout: Option<Duration>,
+}
+
+/// The server a connection was opened to, if not the default one.
+#[derive(Clone, Debug)]
+pub(crate) enum Destination {
+    /// An address passed to `open2`.
+    Raw(OwnedAddress),
+    /// An address passed to `open_address`. It is resolved on every open, so
+    /// a reconnect finds the server again if its hostname moved.
+    Named(Address),
+}
+
+/// A connection handed over from the thread that opened it.
+struct Opened(*mut SPDConnection);
+
+// The connection isn't used by the opening thread once it is sent.
+unsafe impl Send for Opened {}
+
+impl OpenParams {
+    /// Opens a new libspeechd connection.
+    pub fn open(&self) -> Result<*mut SPDConnection, Error> {
+        let address = match &self.address {
+            Some(Destination::Named(address)) => Some(OwnedAddress::resolve(address)?),
+            Some(Destination::Raw(address)) => Some(address.clone()),
+            None => None,
+        };
+        let raw = match self.connect_timeout {
+            None => self.open_to(address.as_ref()),
+            Some(timeout) => {
+                // libspeechd connects with a blocking call that has no
+                // timeout of its own, so it runs on a thread that closes
+                // the connection if it arrives too late.
+                let params = self.clone();
+                let (tx, rx) = mpsc::sync_channel(0);
+                thread::spawn(move || {
+                    let opened = Opened(params.open_to(address.as_ref()));
+                    if let Err(mpsc::SendError(late)) = tx.send(opened) {
+                        if !late.0.is_null() {
+                            unsafe { spd_close(late.0) };
+                        }
+                    }
+                });
+                rx.recv_timeout(timeout).map_err(|_| Error::Timeout)?.0
+            }
+        };
+        if raw.is_null() {
+            Err(Error::Disconnected)
+        } else {
+            Ok(raw)
+        }
+    }
+
+    /// Opens a connection with `spd_open` or `spd_open2`, which is null on
+    /// failure.
+    fn open_to(&self, address: Option<&OwnedAddress>) -> *mut SPDConnection {
+        match address {
+            None if self.autospawn => unsafe {
+                spd_open(
+                    self.client_name.as_ptr(),
+                    self.connection_name.as_ptr(),
+                    self.user_name.as_ptr(),
+                    self.mode as u32,
+                )
+            },
+            address => unsafe {
+                let mut raw = address.map(OwnedAddress::to_raw);
+                let address = raw
+                    .as_mut()
+                    .map_or(ptr::null_mut(), |a| a as *mut SPDConnectionAddress);
+                let mut error_result: *mut c_char = ptr::null_mut();
+                let c = spd_open2(
+                    self.client_name.as_ptr(),
+                    self.connection_name.as_ptr(),
+                    self.user_name.as_ptr(),
+                    self.mode as u32,
+                    address,
+                    self.autospawn as i32,
+                    &mut error_result,
+                );
+                if !error_result.is_null() {
+                    libc::free(error_result as *mut libc::c_void);
+                }
+                c
+            },
+        }
+    }
+}
+
+/// Fails with `Error::Unsupported` if `address` is an IPv6 literal, which
+/// libspeechd can't connect to.
+pub(crate) fn check_ipv4(address: &Address) -> Result<(), Error> {
+    match address {
+        Address::Inet { host, .. } if host.parse::<Ipv6Addr>().is_ok() => {
+            Err(Error::Unsupported(Feature::Ipv6))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A copy of an `SPDConnectionAddress` that doesn't borrow the caller's
+/// strings.
+#[derive(Clone, Debug)]
+pub(crate) struct OwnedAddress {
+    method: SPDConnectionMethod,
+    unix_socket_name: Option<CString>,
+    inet_socket_host: Option<CString>,
+    inet_socket_port: i32,
+    dbus_bus: Option<CString>,
+}
+
+impl OwnedAddress {
+    pub fn unix(path: &Path) -> Self {
+        Self {
+            method: SPDConnectionMethod_SPD_METHOD_UNIX_SOCKET,
+            unix_socket_name: Some(CString::new(path.as_os_str().as_bytes()).unwrap()),
+            inet_socket_host: None,
+            inet_socket_port: 0,
+            dbus_bus: None,
+        }
+    }
+
+    pub fn inet(host: &str, port: u16) -> Self {
+        Self {
+            method: SPDConnectionMethod_SPD_METHOD_INET_SOCKET,
+            unix_socket_name: None,
+            inet_socket_host: Some(CString::new(host).unwrap()),
+            inet_socket_port: port.into(),
+            dbus_bus: None,
+        }
+    }
+
+    /// Turns an address into one libspeechd accepts. It only takes IPv4
+    /// literals, so hostnames are resolved here, and IPv6 fails with
+    /// `Error::Unsupported`.
+    pub fn resolve(address: &Address) -> Result<Self, Error> {
+        let (host, port) = match address {
+            Address::Unix(path) => return Ok(Self::unix(path)),
+            Address::Inet { host, port } => (host, *port),
+        };
+        check_ipv4(address)?;
+        let addrs = address.resolve()?;
+        match addrs.iter().find(|a| a.is_ipv4()) {
+            Some(addr) => Ok(Self::inet(&addr.ip().to_string(), port)),
+            None if !addrs.is_empty() => Err(Error::Unsupported(Feature::Ipv6)),
+            None => Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("{} has no address", host),
+            )
+            .into()),
+        }
+    }
+
+    pub unsafe fn from_raw(address: &SPDConnectionAddress) -> Self {
+        let copy = |s: *mut c_char| {
+            if s.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(s).to_owned())
+            }
+        };
+        Self {
+            method: address.method,
+            unix_socket_name: copy(address.unix_socket_name),
+            inet_socket_host: copy(address.inet_socket_host),
+            inet_socket_port: address.inet_socket_port,
+            dbus_bus: copy(address.dbus_bus),
+        }
+    }
+
+    /// The returned address points into `self` and must not outlive it.
+    fn to_raw(&self) -> SPDConnectionAddress {
+        let ptr =
+            |s: &Option<CString>| s.as_ref().map_or(ptr::null_mut(), |s| s.as_ptr() as *mut _);
+        SPDConnectionAddress {
+            method: self.method,
+            unix_socket_name: ptr(&self.unix_socket_name),
+            inet_socket_host: ptr(&self.inet_socket_host),
+            inet_socket_port: self.inet_socket_port,
+            dbus_bus: ptr(&self.dbus_bus),
+        }
+    }
+}
+
+/// Whether the server end of the socket is still open. A failed call alone
+/// doesn't tell, since the server also rejects commands it doesn't like.
+pub(crate) unsafe fn is_alive(raw: *mut SPDConnection) -> bool {
+    let mut byte = 0u8;
+    let n = libc::recv(
+        (*raw).socket,
+        &mut byte as *mut u8 as *mut libc::c_void,
+        1,
+        libc::MSG_PEEK | libc::MSG_DONTWAIT,
+    );
+    match n {
+        0 => false,
+        n if n > 0 => true,
+        _ => {
+            let err = std::io::Error::last_os_error().raw_os_error();
+            err == Some(libc::EAGAIN) || err == Some(libc::EWOULDBLOCK) || err == Some(libc::EINTR)
+        }
+    }
+}
+
+impl State {
+    /// Runs one command, reopening the connection first if it turns out to
+    /// be dead.
+    pub(crate) fn run<T>(
+        &mut self,
+        client_id: &AtomicU64,
+        timeout: Option<Duration>,
+        f: &impl Fn(*mut SPDConnection) -> T,
+        failed: &impl Fn(&T) -> bool,
+        notice: &mut Option<Notice>,
+    ) -> Result<T, Error> {
+        if self.closed {
+            return Err(Error::Disconnected);
+        }
+        if !self.raw.is_null() {
+            match self.attempt(timeout, f) {
+                Ok(v) if !failed(&v) || unsafe { is_alive(self.raw) } => return Ok(v),
+                Ok(_) => (),
+                Err(e) => {
+                    self.mark_lost(client_id, notice);
+                    return Err(e);
+                }
+            }
+        }
+        let old_id = client_id.load(Ordering::SeqCst);
+        match self.reconnect(client_id) {
+            Some(id) => {
+                self.lost = false;
+                *notice = Some(Notice::Reconnected { old_id, new_id: id });
+                self.attempt(timeout, f)
+            }
+            None => {
+                self.mark_lost(client_id, notice);
+                Err(Error::Disconnected)
+            }
+        }
+    }
+
+    fn attempt<T>(
+        &mut self,
+        timeout: Option<Duration>,
+        f: &impl Fn(*mut SPDConnection) -> T,
+    ) -> Result<T, Error> {
+        let watchdog = match (timeout, &self.watchdog) {
+            (Some(timeout), Some(watchdog)) => {
+                watchdog.arm(unsafe { (*self.raw).socket }, timeout);
+                Some(watchdog)
+            }
+            _ => None,
+        };
+        let v = f(self.raw);
+        match watchdog {
+            Some(watchdog) if watchdog.disarm() => Err(Error::Timeout),
+            _ => Ok(v),
+        }
+    }
+
+    fn mark_lost(&mut self, client_id: &AtomicU64, notice: &mut Option<Notice>) {
+        if !self.lost {
+            self.lost = true;
+            *notice = Some(Notice::ConnectionLost(client_id.load(Ordering::SeqCst)));
+        }
+    }
+
+    /// Replaces a dead connection with a new one and brings it back to the
+    /// old state, returning the new client id. Returns `None` if there is no
+    /// policy, it's too early to try again, or the attempt failed.
+    ///
+    /// The callbacks stay under the old id. Moving them takes the callbacks
+    /// lock, which callbacks hold while calling back into the connection, so
+    /// the caller does it once the state lock is released.
+    pub(crate) fn reconnect(&mut self, client_id: &AtomicU64) -> Option<u64> {
+        let policy = self.reconnect.clone()?;
+        if let Some(max) = policy.max_attempts {
+            if self.failures >= max {
+                return None;
+            }
+        }
+        if let Some(at) = self.retry_at {
+            if Instant::now() < at {
+                return None;
+            }
+        }
+        let raw = match self.params.open() {
+            Ok(raw) => raw,
+            Err(_) => {
+                self.failures += 1;
+                self.retry_at = Some(Instant::now() + policy.delay(self.failures));
+                return None;
+            }
+        };
+        self.failures = 0;
+        self.retry_at = None;
+        if !self.raw.is_null() {
+            unsafe { spd_close(self.raw) };
+        }
+        self.raw = raw;
+        // A block doesn't survive the connection it was opened on.
+        self.in_block = false;
+
+        let new_id = unsafe { setup(raw) };
+        client_id.store(new_id, Ordering::SeqCst);
+        self.replay();
+        Some(new_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_keeps_ipv4_literals() {
+        let address =
+            OwnedAddress::resolve(&"inet_socket:127.0.0.1:6561".parse().unwrap()).unwrap();
+        assert_eq!(address.inet_socket_host.unwrap().to_str(), Ok("127.0.0.1"));
+        assert_eq!(address.inet_socket_port, 6561);
+    }
+
+    #[test]
+    fn resolve_refuses_ipv6() {
+        let address = "inet_socket:[::1]:6560".parse().unwrap();
+        match OwnedAddress::resolve(&address) {
+            Err(Error::Unsupported(Feature::Ipv6)) => (),
+            other => panic!("expected IPv6 to be unsupported, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn open_address_refuses_ipv6() {
+        let address = "inet_socket:[::1]:6560".parse().unwrap();
+        let opened =
+            crate::Connection::open_address("test", "main", "user", Mode::Single, &address, None);
+        match opened {
+            Err(Error::Unsupported(Feature::Ipv6)) => (),
+            other => panic!("expected IPv6 to be unsupported, got {:?}", other),
+        }
+    }
+}
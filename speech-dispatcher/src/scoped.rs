@@ -0,0 +1,322 @@
+//! Setting changes that are undone when a guard goes out of scope.
+
+use std::ops::Deref;
+
+use crate::history::parse_first_number;
+use crate::ssip::on_off;
+use crate::{
+    CapitalLetters, Connection, DataMode, Error, LanguageTag, Punctuation, Settings, VoiceType,
+};
+
+/// The settings a guard can change, in the order they are restored. The
+/// output module goes first since changing it resets the voice, and so
+/// does the language.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Field {
+    OutputModule,
+    Language,
+    Voice,
+    Rate,
+    Pitch,
+    Volume,
+    Punctuation,
+    CapitalLetters,
+    Spelling,
+    DataMode,
+}
+
+impl Field {
+    /// Copies this setting from `from`.
+    fn copy(self, to: &mut Settings, from: &Settings) {
+        match self {
+            Field::OutputModule => to.output_module = from.output_module.clone(),
+            Field::Language => to.language = from.language.clone(),
+            Field::Voice => {
+                to.synthesis_voice = from.synthesis_voice.clone();
+                to.voice_type = from.voice_type;
+            }
+            Field::Rate => to.rate = from.rate,
+            Field::Pitch => to.pitch = from.pitch,
+            Field::Volume => to.volume = from.volume,
+            Field::Punctuation => to.punctuation = from.punctuation,
+            Field::CapitalLetters => to.capital_letters = from.capital_letters,
+            Field::Spelling => to.spelling = from.spelling,
+            Field::DataMode => to.data_mode = from.data_mode,
+        }
+    }
+}
+
+/// Changes settings until dropped, then puts back the values they had when
+/// the guard was made. Guards made from a guard nest, each restoring what
+/// it changed.
+///
+/// The setters shadow those of the connection the guard derefs to. Values
+/// this connection never set are asked of the server when first changed.
+/// SSIP can't read back punctuation, capital letters or spelling, so those
+/// are only restored if this connection set them before the guard was made;
+/// otherwise the guard's value stays. The data mode goes back to text, which
+/// every connection starts in, unless it was set before.
+#[derive(Debug)]
+pub struct Scoped<'a> {
+    connection: &'a Connection,
+    saved: Settings,
+    /// The commands that undo each change, one per setting.
+    undo: Vec<(Field, String)>,
+}
+
+impl Connection {
+    pub fn scoped(&self) -> Scoped<'_> {
+        Scoped {
+            connection: self,
+            saved: self.settings(),
+            undo: Vec::new(),
+        }
+    }
+}
+
+impl Scoped<'_> {
+    fn get(&self, name: &str) -> Result<String, Error> {
+        Ok(self
+            .connection
+            .command(&format!("GET {}", name))?
+            .value()?
+            .to_string())
+    }
+
+    fn get_number(&self, name: &str) -> Result<String, Error> {
+        let value: i32 = parse_first_number(
+            &name.to_lowercase(),
+            &self.connection.command(&format!("GET {}", name))?,
+        )?;
+        Ok(value.to_string())
+    }
+
+    /// Sends `SET SELF name value` and records it in the connection's
+    /// settings, remembering how to undo it the first time `field` changes.
+    /// `undo` gives `None` when the previous value isn't known.
+    fn set(
+        &mut self,
+        field: Field,
+        name: &str,
+        value: &str,
+        undo: impl FnOnce(&Self) -> Result<Option<String>, Error>,
+        record: impl FnOnce(&mut Settings),
+    ) -> Result<(), Error> {
+        let undo = if self.undo.iter().any(|(f, _)| *f == field) {
+            None
+        } else {
+            undo(self)?
+        };
+        self.connection
+            .command(&format!("SET SELF {} {}", name, value))?;
+        record(&mut self.connection.state().settings);
+        if let Some(undo) = undo {
+            self.undo.push((field, undo));
+        }
+        Ok(())
+    }
+
+    pub fn set_output_module<S: Into<String>>(&mut self, output_module: S) -> Result<(), Error> {
+        let output_module = output_module.into();
+        let saved = self.saved.output_module.clone();
+        self.set(
+            Field::OutputModule,
+            "OUTPUT_MODULE",
+            &output_module,
+            |s| {
+                let previous = match saved {
+                    Some(v) => v,
+                    None => s.get("OUTPUT_MODULE")?,
+                };
+                Ok(Some(format!("SET SELF OUTPUT_MODULE {}", previous)))
+            },
+            |s| s.output_module = Some(output_module.clone()),
+        )
+    }
+
+    pub fn set_language(&mut self, language: &LanguageTag) -> Result<(), Error> {
+        let saved = self.saved.language.clone();
+        self.set(
+            Field::Language,
+            "LANGUAGE",
+            language.as_str(),
+            |s| {
+                let previous = match saved {
+                    Some(v) => v.to_string(),
+                    None => s.get("LANGUAGE")?,
+                };
+                Ok(Some(format!("SET SELF LANGUAGE {}", previous)))
+            },
+            |s| s.language = Some(language.clone()),
+        )
+    }
+
+    /// The command that brings back the voice in use when the guard was
+    /// made.
+    fn undo_voice(&self) -> Result<Option<String>, Error> {
+        let command = match (&self.saved.synthesis_voice, self.saved.voice_type) {
+            (Some(v), _) => format!("SET SELF SYNTHESIS_VOICE {}", v),
+            (None, Some(v)) => format!("SET SELF VOICE_TYPE {}", v.as_ssip()),
+            (None, None) => format!("SET SELF VOICE_TYPE {}", self.get("VOICE_TYPE")?),
+        };
+        Ok(Some(command))
+    }
+
+    pub fn set_voice_type(&mut self, voice_type: VoiceType) -> Result<(), Error> {
+        self.set(
+            Field::Voice,
+            "VOICE_TYPE",
+            voice_type.as_ssip(),
+            Self::undo_voice,
+            |s| {
+                s.voice_type = Some(voice_type);
+                s.synthesis_voice = None;
+            },
+        )
+    }
+
+    pub fn set_synthesis_voice<S: Into<String>>(&mut self, voice_name: S) -> Result<(), Error> {
+        let voice_name = voice_name.into();
+        self.set(
+            Field::Voice,
+            "SYNTHESIS_VOICE",
+            &voice_name,
+            Self::undo_voice,
+            |s| {
+                s.synthesis_voice = Some(voice_name.clone());
+                s.voice_type = None;
+            },
+        )
+    }
+
+    pub fn set_voice_rate(&mut self, rate: i32) -> Result<(), Error> {
+        let saved = self.saved.rate;
+        self.set(
+            Field::Rate,
+            "RATE",
+            &rate.to_string(),
+            |s| {
+                let previous = match saved {
+                    Some(v) => v.to_string(),
+                    None => s.get_number("RATE")?,
+                };
+                Ok(Some(format!("SET SELF RATE {}", previous)))
+            },
+            |s| s.rate = Some(rate),
+        )
+    }
+
+    pub fn set_voice_pitch(&mut self, pitch: i32) -> Result<(), Error> {
+        let saved = self.saved.pitch;
+        self.set(
+            Field::Pitch,
+            "PITCH",
+            &pitch.to_string(),
+            |s| {
+                let previous = match saved {
+                    Some(v) => v.to_string(),
+                    None => s.get_number("PITCH")?,
+                };
+                Ok(Some(format!("SET SELF PITCH {}", previous)))
+            },
+            |s| s.pitch = Some(pitch),
+        )
+    }
+
+    pub fn set_volume(&mut self, volume: i32) -> Result<(), Error> {
+        let saved = self.saved.volume;
+        self.set(
+            Field::Volume,
+            "VOLUME",
+            &volume.to_string(),
+            |s| {
+                let previous = match saved {
+                    Some(v) => v.to_string(),
+                    None => s.get_number("VOLUME")?,
+                };
+                Ok(Some(format!("SET SELF VOLUME {}", previous)))
+            },
+            |s| s.volume = Some(volume),
+        )
+    }
+
+    pub fn set_punctuation(&mut self, punctuation: Punctuation) -> Result<(), Error> {
+        let previous = self.saved.punctuation;
+        self.set(
+            Field::Punctuation,
+            "PUNCTUATION",
+            punctuation.as_ssip(),
+            |_| Ok(previous.map(|v| format!("SET SELF PUNCTUATION {}", v.as_ssip()))),
+            |s| s.punctuation = Some(punctuation),
+        )
+    }
+
+    pub fn set_capital_letters(&mut self, capital_letters: CapitalLetters) -> Result<(), Error> {
+        let previous = self.saved.capital_letters;
+        self.set(
+            Field::CapitalLetters,
+            "CAP_LET_RECOGN",
+            capital_letters.as_ssip(),
+            |_| Ok(previous.map(|v| format!("SET SELF CAP_LET_RECOGN {}", v.as_ssip()))),
+            |s| s.capital_letters = Some(capital_letters),
+        )
+    }
+
+    pub fn set_spelling(&mut self, spelling: bool) -> Result<(), Error> {
+        let previous = self.saved.spelling;
+        self.set(
+            Field::Spelling,
+            "SPELLING",
+            on_off(spelling),
+            |_| Ok(previous.map(|v| format!("SET SELF SPELLING {}", on_off(v)))),
+            |s| s.spelling = Some(spelling),
+        )
+    }
+
+    pub fn set_data_mode(&mut self, mode: DataMode) -> Result<(), Error> {
+        let previous = self.saved.data_mode.unwrap_or(DataMode::Text);
+        self.set(
+            Field::DataMode,
+            "SSML_MODE",
+            mode.ssml_mode(),
+            |_| Ok(Some(format!("SET SELF SSML_MODE {}", previous.ssml_mode()))),
+            |s| s.data_mode = Some(mode),
+        )
+    }
+
+    /// Puts back the settings now instead of when dropped, failing with the
+    /// first command that did.
+    pub fn restore(mut self) -> Result<(), Error> {
+        self.undo_all()
+    }
+
+    /// Sends the undo commands, the output module first. Settings whose
+    /// command failed stay recorded with the value the guard set.
+    fn undo_all(&mut self) -> Result<(), Error> {
+        self.undo.sort_by_key(|(field, _)| *field);
+        let _op = self.connection.serialize();
+        let mut result = Ok(());
+        for (field, command) in self.undo.drain(..) {
+            match self.connection.command(&command) {
+                Ok(_) => field.copy(&mut self.connection.state().settings, &self.saved),
+                Err(e) if result.is_ok() => result = Err(e),
+                Err(_) => (),
+            }
+        }
+        result
+    }
+}
+
+impl Deref for Scoped<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection
+    }
+}
+
+impl Drop for Scoped<'_> {
+    fn drop(&mut self) {
+        let _ = self.undo_all();
+    }
+}
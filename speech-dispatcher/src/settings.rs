@@ -0,0 +1,105 @@
+//! The settings a connection changed, kept so they survive a reconnect.
+
+use std::ffi::CString;
+
+use speech_dispatcher_sys::*;
+
+use crate::{CapitalLetters, DataMode, LanguageTag, Notification, Punctuation, State, VoiceType};
+
+/// Settings changed through the `Connection` setters that apply to this
+/// client. `None` means the setting was left at the server's default.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    pub output_module: Option<String>,
+    pub language: Option<LanguageTag>,
+    pub voice_type: Option<VoiceType>,
+    pub synthesis_voice: Option<String>,
+    pub rate: Option<i32>,
+    pub pitch: Option<i32>,
+    pub pitch_range: Option<i32>,
+    pub volume: Option<i32>,
+    pub punctuation: Option<Punctuation>,
+    pub capital_letters: Option<CapitalLetters>,
+    pub spelling: Option<bool>,
+    pub data_mode: Option<DataMode>,
+    pub pause_context: Option<u32>,
+}
+
+const NOTIFICATIONS: [Notification; 6] = [
+    Notification::Begin,
+    Notification::End,
+    Notification::IndexMarks,
+    Notification::Cancel,
+    Notification::Pause,
+    Notification::Resume,
+];
+
+impl State {
+    /// Sends the recorded settings to a freshly opened connection. The output
+    /// module goes first since changing it resets the voice.
+    pub(crate) fn replay(&self) {
+        let c = self.raw;
+        let s = &self.settings;
+        let cstr = |v: &str| CString::new(v).unwrap();
+        unsafe {
+            if let Some(v) = &s.output_module {
+                spd_set_output_module(c, cstr(v).as_ptr());
+            }
+            if let Some(v) = &s.language {
+                spd_set_language(c, cstr(v.as_str()).as_ptr());
+            }
+            if let Some(v) = s.voice_type {
+                spd_set_voice_type(c, v as SPDVoiceType::Type);
+            }
+            if let Some(v) = &s.synthesis_voice {
+                spd_set_synthesis_voice(c, cstr(v).as_ptr());
+            }
+            if let Some(v) = s.rate {
+                spd_set_voice_rate(c, v);
+            }
+            if let Some(v) = s.pitch {
+                spd_set_voice_pitch(c, v);
+            }
+            if let Some(v) = s.volume {
+                spd_set_volume(c, v);
+            }
+            if let Some(v) = s.punctuation {
+                spd_set_punctuation(c, v as u32);
+            }
+            if let Some(v) = s.capital_letters {
+                spd_set_capital_letters(c, v as u32);
+            }
+            if let Some(v) = s.spelling {
+                let v = if v {
+                    SPDSpelling::SPD_SPELL_ON
+                } else {
+                    SPDSpelling::SPD_SPELL_OFF
+                };
+                spd_set_spelling(c, v);
+            }
+            if let Some(v) = s.data_mode {
+                spd_set_data_mode(c, v as u32);
+            }
+            // Pitch range and pause context have no function before 0.11,
+            // so they go as plain commands on every release.
+            let commands = [
+                ("PITCH_RANGE", s.pitch_range.map(|v| v.to_string())),
+                ("PAUSE_CONTEXT", s.pause_context.map(|v| v.to_string())),
+            ];
+            for (name, value) in commands.iter() {
+                if let Some(v) = value {
+                    let data = cstr(&format!("SET SELF {} {}\r\n", name, v));
+                    let reply = spd_send_data(c, data.as_ptr(), SPD_WAIT_REPLY as i32);
+                    if !reply.is_null() {
+                        libc::free(reply as *mut libc::c_void);
+                    }
+                }
+            }
+            for n in NOTIFICATIONS.iter() {
+                if self.notifications & *n as u32 == 0 {
+                    spd_set_notification_off(c, *n as u32);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Sound icons: short sounds the output modules play by name.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+
+use crate::{Connection, Error, Priority};
+
+/// The name of a sound icon, which is the name of its file in the icon
+/// directory.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SoundIcon(String);
+
+impl SoundIcon {
+    /// Fails for names that can't be sent in a `SOUND_ICON` command or
+    /// would point outside the icon directory.
+    pub fn new<S: Into<String>>(name: S) -> Result<Self, Error> {
+        let name = name.into();
+        if name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.contains('/')
+            || name.chars().any(|c| c.is_whitespace() || c.is_control())
+        {
+            return Err(Error::InvalidSoundIcon(name));
+        }
+        Ok(Self(name))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SoundIcon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<SoundIcon> for String {
+    fn from(icon: SoundIcon) -> Self {
+        icon.0
+    }
+}
+
+/// Icons tried for the built-in event names, in order of preference. The
+/// names are those of the sound-icons package.
+const EVENTS: &[(&str, &[&str])] = &[
+    ("error", &["error", "gavel", "bark", "bell"]),
+    ("warning", &["warning", "bark", "bell"]),
+    (
+        "success",
+        &["success", "finish", "chord-ascending", "glass"],
+    ),
+    (
+        "new-message",
+        &["new-message", "message", "canary-short", "cuckoo"],
+    ),
+    ("prompt", &["prompt", "start", "bell"]),
+];
+
+/// The sound icons available to the server, and which of them to play for
+/// named events such as `error` or `new-message`.
+#[derive(Clone, Debug)]
+pub struct SoundIconCatalog {
+    icons: BTreeMap<SoundIcon, PathBuf>,
+    events: HashMap<String, Vec<String>>,
+}
+
+impl SoundIconCatalog {
+    /// Where the sound-icons package installs its files, and the folder the
+    /// output modules use unless configured otherwise.
+    pub const DEFAULT_DIR: &'static str = "/usr/share/sounds/sound-icons";
+
+    /// An empty catalogue with the default event mappings.
+    pub fn new() -> Self {
+        let events = EVENTS
+            .iter()
+            .map(|(event, icons)| {
+                let icons = icons.iter().map(|icon| icon.to_string()).collect();
+                (event.to_string(), icons)
+            })
+            .collect();
+        Self {
+            icons: BTreeMap::new(),
+            events,
+        }
+    }
+
+    /// Catalogues the icons in one directory.
+    pub fn scan<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let mut catalog = Self::new();
+        catalog.add_dir(dir.as_ref())?;
+        Ok(catalog)
+    }
+
+    /// Catalogues the icon folders set in the output module configuration,
+    /// system-wide and for the current user, or the default folder if none
+    /// is set. Folders that don't exist are skipped.
+    pub fn discover() -> io::Result<Self> {
+        let mut dirs = configured_dirs();
+        if dirs.is_empty() {
+            dirs.push(PathBuf::from(Self::DEFAULT_DIR));
+        }
+        let mut catalog = Self::new();
+        for dir in dirs {
+            match catalog.add_dir(&dir) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                r => r?,
+            }
+        }
+        Ok(catalog)
+    }
+
+    fn add_dir(&mut self, dir: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.path().is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if let Ok(icon) = SoundIcon::new(name) {
+                    self.icons.entry(icon).or_insert_with(|| entry.path());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn icons(&self) -> impl Iterator<Item = &SoundIcon> {
+        self.icons.keys()
+    }
+
+    /// The file an icon plays.
+    pub fn path(&self, icon: &SoundIcon) -> Option<&Path> {
+        self.icons.get(icon).map(PathBuf::as_path)
+    }
+
+    /// Looks an icon up by its file name, or by the name without its
+    /// extension.
+    pub fn get(&self, name: &str) -> Option<SoundIcon> {
+        let icon = SoundIcon::new(name).ok()?;
+        if self.icons.contains_key(&icon) {
+            return Some(icon);
+        }
+        self.icons
+            .keys()
+            .find(|icon| Path::new(icon.name()).file_stem().and_then(|s| s.to_str()) == Some(name))
+            .cloned()
+    }
+
+    /// Sets the icons tried for an event, in order of preference.
+    pub fn map_event<S: Into<String>>(&mut self, event: S, icons: &[&str]) {
+        let icons = icons.iter().map(|icon| icon.to_string()).collect();
+        self.events.insert(event.into(), icons);
+    }
+
+    /// The first available icon for an event. An icon named like the event
+    /// is used for events that have no mapping.
+    pub fn for_event(&self, event: &str) -> Option<SoundIcon> {
+        match self.events.get(event) {
+            Some(icons) => icons.iter().find_map(|icon| self.get(icon)),
+            None => self.get(event),
+        }
+    }
+}
+
+lazy_static! {
+    /// The icons `discover` finds, looked up on first use.
+    static ref DISCOVERED: SoundIconCatalog = SoundIconCatalog::discover().unwrap_or_default();
+}
+
+/// The icon to send for `name`, found in `catalog` as `get` finds it. Fails
+/// with `Error::InvalidSoundIcon` for names that can't be sent, and with
+/// `Error::UnknownSoundIcon` for icons the catalogue doesn't have. An empty
+/// catalogue, as when no icon folder is installed on this machine, can't
+/// tell, so any valid name is sent.
+pub(crate) fn resolve(catalog: &SoundIconCatalog, name: String) -> Result<SoundIcon, Error> {
+    let icon = SoundIcon::new(name)?;
+    if catalog.icons.is_empty() {
+        return Ok(icon);
+    }
+    catalog
+        .get(icon.name())
+        .ok_or_else(|| Error::UnknownSoundIcon(icon.into()))
+}
+
+/// `resolve` against the discovered icons.
+pub(crate) fn resolve_discovered(name: String) -> Result<SoundIcon, Error> {
+    resolve(&DISCOVERED, name)
+}
+
+impl Default for SoundIconCatalog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The `*SoundIconFolder` settings found in the module configuration files.
+fn configured_dirs() -> Vec<PathBuf> {
+    let mut config_dirs = vec![PathBuf::from("/etc/speech-dispatcher/modules")];
+    let user_config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    if let Some(config) = user_config {
+        config_dirs.push(config.join("speech-dispatcher/modules"));
+    }
+    let mut dirs = Vec::new();
+    for config_dir in config_dirs {
+        let entries = match fs::read_dir(&config_dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let config = match fs::read_to_string(entry.path()) {
+                Ok(config) => config,
+                Err(_) => continue,
+            };
+            for line in config.lines() {
+                let mut fields = line.trim().splitn(2, char::is_whitespace);
+                let (key, value) = match (fields.next(), fields.next()) {
+                    (Some(key), Some(value)) => (key, value),
+                    _ => continue,
+                };
+                if key.starts_with('#') || !key.ends_with("SoundIconFolder") {
+                    continue;
+                }
+                let dir = PathBuf::from(value.trim().trim_matches('"'));
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+    }
+    dirs
+}
+
+impl Connection {
+    /// Plays the icon the catalogue picks for `event`, failing with
+    /// `Error::UnknownSoundIcon` if none of its icons is available or with
+    /// the server's reply if it refuses to play it.
+    pub fn sound_icon_event(
+        &self,
+        priority: Priority,
+        catalog: &SoundIconCatalog,
+        event: &str,
+    ) -> Result<(), Error> {
+        let icon = catalog
+            .for_event(event)
+            .ok_or_else(|| Error::UnknownSoundIcon(event.to_string()))?;
+        let _op = self.serialize();
+        self.command(&format!("SET SELF PRIORITY {}", priority.as_ssip()))?;
+        self.command(&format!("SOUND_ICON {}", icon)).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_names_outside_the_icon_directory() {
+        for name in [
+            "",
+            ".",
+            "..",
+            "../bell",
+            "a/b",
+            "two words",
+            "tab\t",
+            "nul\0",
+        ] {
+            assert!(SoundIcon::new(name).is_err(), "{:?}", name);
+        }
+        assert_eq!(SoundIcon::new("bell.wav").unwrap().name(), "bell.wav");
+        assert!(SoundIcon::new(".hidden").is_ok());
+    }
+
+    #[test]
+    fn finds_icons_by_stem_and_event() {
+        let dir = env::temp_dir().join(format!("sound-icons-{}", std::process::id()));
+        fs::create_dir_all(dir.join("subdir")).unwrap();
+        fs::write(dir.join("bark.wav"), b"").unwrap();
+        fs::write(dir.join("prompt"), b"").unwrap();
+        let mut catalog = SoundIconCatalog::scan(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = catalog.icons().map(SoundIcon::name).collect();
+        assert_eq!(names, ["bark.wav", "prompt"]);
+        assert_eq!(catalog.get("bark").unwrap().name(), "bark.wav");
+        assert_eq!(catalog.for_event("warning").unwrap().name(), "bark.wav");
+        assert_eq!(catalog.for_event("prompt").unwrap().name(), "prompt");
+        assert_eq!(catalog.for_event("success"), None);
+        catalog.map_event("success", &["missing", "prompt"]);
+        assert_eq!(catalog.for_event("success").unwrap().name(), "prompt");
+    }
+
+    #[test]
+    fn resolve_checks_the_catalogue() {
+        let dir = env::temp_dir().join(format!("sound-icons-resolve-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("bell.wav"), b"").unwrap();
+        let catalog = SoundIconCatalog::scan(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            resolve(&catalog, "bell".to_string()).unwrap().name(),
+            "bell.wav"
+        );
+        assert!(matches!(
+            resolve(&catalog, "gavel".to_string()),
+            Err(Error::UnknownSoundIcon(name)) if name == "gavel"
+        ));
+        assert!(matches!(
+            resolve(&catalog, "../bell".to_string()),
+            Err(Error::InvalidSoundIcon(_))
+        ));
+        let empty = SoundIconCatalog::new();
+        assert_eq!(
+            resolve(&empty, "gavel".to_string()).unwrap().name(),
+            "gavel"
+        );
+    }
+
+    #[test]
+    fn sound_icon_rejects_invalid_names() {
+        let connection = crate::tests::dead_connection(u64::MAX - 20);
+        for name in ["../../etc/passwd", "nul\0"] {
+            assert!(matches!(
+                connection.sound_icon(Priority::Text, name),
+                Err(Error::InvalidSoundIcon(_))
+            ));
+        }
+        assert!(!connection.state().lost);
+    }
+}
@@ -0,0 +1,263 @@
+//! Pieces of the Speech Synthesis Interface Protocol shared by the clients.
+
+use crate::{DataMode, Error, Event};
+
+/// A complete reply to a command: any number of `NNN-data` lines followed
+/// by a final `NNN message` line.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Reply {
+    pub code: u16,
+    pub lines: Vec<String>,
+    pub message: String,
+}
+
+impl Reply {
+    pub fn is_ok(&self) -> bool {
+        (200..300).contains(&self.code)
+    }
+
+    pub fn into_result(self) -> Result<Self, Error> {
+        if self.is_ok() {
+            Ok(self)
+        } else {
+            Err(Error::Server {
+                code: self.code,
+                message: self.message,
+            })
+        }
+    }
+
+    /// The first data line, which is where single-valued replies such as
+    /// `GET RATE` or `SPEAK` put their value.
+    pub fn value(&self) -> Result<&str, Error> {
+        self.lines
+            .first()
+            .map(String::as_str)
+            .ok_or_else(|| Error::Protocol(format!("reply {} carries no data", self.code)))
+    }
+
+    /// Parses a reply as returned by `spd_send_data`.
+    pub fn parse(raw: &str) -> Result<Self, Error> {
+        let mut assembler = Assembler::default();
+        for line in raw.split("\r\n").filter(|line| !line.is_empty()) {
+            if let Some(Message::Reply(reply)) = assembler.push(line)? {
+                return Ok(reply);
+            }
+        }
+        Err(Error::Protocol(format!("incomplete reply {:?}", raw)))
+    }
+
+    pub fn to_raw(&self) -> String {
+        let mut raw = String::new();
+        for line in &self.lines {
+            raw.push_str(&format!("{}-{}\r\n", self.code, line));
+        }
+        raw.push_str(&format!("{} {}\r\n", self.code, self.message));
+        raw
+    }
+}
+
+/// Splits one line (without its `\r\n`) into its code, whether it ends the
+/// reply, and its text.
+pub(crate) fn parse_line(line: &str) -> Result<(u16, bool, &str), Error> {
+    let invalid = || Error::Protocol(format!("malformed line {:?}", line));
+    if line.len() < 4 || !line.is_char_boundary(3) || !line.is_char_boundary(4) {
+        return Err(invalid());
+    }
+    let code = line[..3].parse::<u16>().map_err(|_| invalid())?;
+    let last = match &line[3..4] {
+        " " => true,
+        "-" => false,
+        _ => return Err(invalid()),
+    };
+    Ok((code, last, &line[4..]))
+}
+
+/// Events use the `7xx` range and may arrive between any two replies.
+pub(crate) fn is_event_code(code: u16) -> bool {
+    (700..800).contains(&code)
+}
+
+/// Accumulates lines until a reply or event is complete.
+#[derive(Debug, Default)]
+pub(crate) struct Assembler {
+    reply: Vec<String>,
+    event: Vec<String>,
+}
+
+pub(crate) enum Message {
+    Reply(Reply),
+    Event(Event),
+}
+
+impl Assembler {
+    pub fn push(&mut self, line: &str) -> Result<Option<Message>, Error> {
+        let (code, last, text) = parse_line(line)?;
+        if is_event_code(code) {
+            if !last {
+                self.event.push(text.to_string());
+                return Ok(None);
+            }
+            let lines = std::mem::take(&mut self.event);
+            return parse_event(code, &lines).map(|e| e.map(Message::Event));
+        }
+        if !last {
+            self.reply.push(text.to_string());
+            return Ok(None);
+        }
+        Ok(Some(Message::Reply(Reply {
+            code,
+            lines: std::mem::take(&mut self.reply),
+            message: text.to_string(),
+        })))
+    }
+}
+
+fn parse_event(code: u16, lines: &[String]) -> Result<Option<Event>, Error> {
+    let id = |i: usize| -> Result<u64, Error> {
+        lines
+            .get(i)
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(|| Error::Protocol(format!("event {} is missing its ids", code)))
+    };
+    let (msg_id, client_id) = (id(0)?, id(1)?);
+    let event = match code {
+        700 => Event::IndexMark {
+            msg_id,
+            client_id,
+            mark: lines.get(2).cloned().unwrap_or_default(),
+        },
+        701 => Event::Begin { msg_id, client_id },
+        702 => Event::End { msg_id, client_id },
+        703 => Event::Cancel { msg_id, client_id },
+        704 => Event::Pause { msg_id, client_id },
+        705 => Event::Resume { msg_id, client_id },
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
+
+/// Prepares text for the data section of `SPEAK`: lines are sent with
+/// `\r\n`, a leading dot is doubled and a lone dot ends the message.
+pub(crate) fn escape_message(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.starts_with('.') {
+            escaped.push('.');
+        }
+        escaped.push_str(line);
+        escaped.push_str("\r\n");
+    }
+    escaped.push_str(".\r\n");
+    escaped
+}
+
+pub(crate) fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+impl DataMode {
+    /// The value of `SSML_MODE` that selects this mode.
+    pub(crate) fn ssml_mode(self) -> &'static str {
+        on_off(matches!(self, DataMode::SSML))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all(assembler: &mut Assembler, lines: &[&str]) -> Vec<Message> {
+        lines
+            .iter()
+            .filter_map(|line| assembler.push(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn assembler_builds_replies() {
+        let mut assembler = Assembler::default();
+        let messages = push_all(
+            &mut assembler,
+            &["249-espeak-ng", "249-dummy", "249 OK MODULE LIST SENT"],
+        );
+        match &messages[..] {
+            [Message::Reply(reply)] => {
+                assert_eq!(reply.code, 249);
+                assert_eq!(reply.lines, ["espeak-ng", "dummy"]);
+                assert_eq!(reply.message, "OK MODULE LIST SENT");
+                assert!(reply.is_ok());
+            }
+            _ => panic!("expected one reply"),
+        }
+    }
+
+    #[test]
+    fn assembler_separates_events_from_replies() {
+        let mut assembler = Assembler::default();
+        let messages = push_all(
+            &mut assembler,
+            &[
+                "225-21",
+                "700-21",
+                "700-3",
+                "700-mark one",
+                "700 INDEX MARK",
+                "225 OK MESSAGE QUEUED",
+            ],
+        );
+        match &messages[..] {
+            [Message::Event(event), Message::Reply(reply)] => {
+                assert_eq!(
+                    *event,
+                    Event::IndexMark {
+                        msg_id: 21,
+                        client_id: 3,
+                        mark: "mark one".to_string(),
+                    }
+                );
+                assert_eq!(reply.value().unwrap(), "21");
+            }
+            _ => panic!("expected an event, then a reply"),
+        }
+    }
+
+    #[test]
+    fn assembler_rejects_malformed_lines() {
+        let mut assembler = Assembler::default();
+        assert!(assembler.push("OK").is_err());
+        assert!(assembler.push("2x5 OK").is_err());
+        assert!(assembler.push("225+OK").is_err());
+        assert!(assembler.push("22é OK").is_err());
+        assert!(assembler.push("701 BEGIN").is_err());
+    }
+
+    #[test]
+    fn unknown_events_are_skipped() {
+        let mut assembler = Assembler::default();
+        assert!(push_all(&mut assembler, &["799-1", "799-2", "799 NEW"]).is_empty());
+    }
+
+    #[test]
+    fn reply_round_trips() {
+        let reply = Reply::parse("208-a\r\n208-b\r\n410 ERR\r\n").unwrap();
+        assert_eq!(reply.code, 410);
+        assert!(reply.into_result().is_err());
+        let raw = "251-rate\r\n251 OK\r\n";
+        assert_eq!(Reply::parse(raw).unwrap().to_raw(), raw);
+        assert!(Reply::parse("251-rate\r\n").is_err());
+    }
+
+    #[test]
+    fn escape_message_doubles_leading_dots() {
+        assert_eq!(escape_message("Hello"), "Hello\r\n.\r\n");
+        assert_eq!(escape_message("a\r\n.b\n."), "a\r\n..b\r\n..\r\n.\r\n");
+        assert_eq!(escape_message(""), "\r\n.\r\n");
+        assert_eq!(escape_message("x.\n"), "x.\r\n\r\n.\r\n");
+    }
+}
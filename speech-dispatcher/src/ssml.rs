@@ -0,0 +1,89 @@
+//! Speaking SSML documents.
+
+use crate::{Connection, DataMode, Error, Priority};
+
+/// Checks that `doc` is well-formed XML with a `<speak>` root element,
+/// which is what the server expects in SSML mode.
+pub(crate) fn check(doc: &str) -> Result<(), Error> {
+    let parsed = roxmltree::Document::parse(doc).map_err(|e| Error::InvalidSsml(e.to_string()))?;
+    let root = parsed.root_element().tag_name().name();
+    if root != "speak" {
+        return Err(Error::InvalidSsml(format!(
+            "root element is <{}>, not <speak>",
+            root
+        )));
+    }
+    Ok(())
+}
+
+impl Connection {
+    /// Speaks an SSML document, switching the data mode to SSML for this
+    /// message only. Markup that isn't well formed is rejected with
+    /// `Error::InvalidSsml` before anything is sent, and a document the
+    /// server refuses comes back as its error reply.
+    pub fn say_ssml<S: Into<String>>(&self, priority: Priority, doc: S) -> Result<u64, Error> {
+        let doc = doc.into();
+        check(&doc)?;
+        let _op = self.serialize();
+        let previous = self.settings().data_mode.unwrap_or(DataMode::Text);
+        let switch = !matches!(previous, DataMode::SSML);
+        if switch {
+            self.command("SET SELF SSML_MODE on")?;
+        }
+        let msg_id = self
+            .command(&format!("SET SELF PRIORITY {}", priority.as_ssip()))
+            .and_then(|_| self.speak(&doc));
+        if switch {
+            let restored = self.command(&format!("SET SELF SSML_MODE {}", previous.ssml_mode()));
+            if msg_id.is_ok() {
+                restored?;
+            }
+        }
+        msg_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_speak_documents() {
+        assert!(check("<speak>Hello</speak>").is_ok());
+        assert!(check(
+            "<?xml version=\"1.0\"?>\n<speak version=\"1.1\" \
+             xmlns=\"http://www.w3.org/2001/10/synthesis\">\
+             Hi <mark name=\"a\"/><break time=\"1s\"/> &amp; bye</speak>"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn rejects_other_roots_and_malformed_markup() {
+        for doc in &[
+            "",
+            "Hello",
+            "<p>Hello</p>",
+            "<speak>Hello",
+            "<speak>a & b</speak>",
+            "<speak><s>Hi</speak></s>",
+            "<speak/><speak/>",
+        ] {
+            assert!(
+                matches!(check(doc), Err(Error::InvalidSsml(_))),
+                "{:?} accepted",
+                doc
+            );
+        }
+    }
+
+    #[test]
+    fn say_ssml_checks_before_sending() {
+        let connection = crate::tests::dead_connection(u64::MAX - 70);
+        assert!(matches!(
+            connection.say_ssml(Priority::Text, "<p>Hi</p>"),
+            Err(Error::InvalidSsml(_))
+        ));
+        assert!(!connection.state().lost);
+    }
+}
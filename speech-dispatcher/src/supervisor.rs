@@ -0,0 +1,189 @@
+//! Controlling other clients' speech.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::{CapitalLetters, ClientInfo, Connection, Error, LanguageTag, Punctuation, VoiceType};
+
+/// The id the server gives a client, as used by the `_uid` methods.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClientId(pub u64);
+
+impl ClientId {
+    /// The id in the type a libspeechd `_uid` function takes, failing with
+    /// `Error::InvalidClientId` if it doesn't fit rather than wrapping
+    /// around to another client.
+    pub(crate) fn uid<T: TryFrom<u64>>(self) -> Result<T, Error> {
+        T::try_from(self.0).map_err(|_| Error::InvalidClientId(self))
+    }
+}
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u64> for ClientId {
+    fn from(id: u64) -> Self {
+        ClientId(id)
+    }
+}
+
+/// The clients a `Supervisor` command applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    All,
+    Client(ClientId),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::All => write!(f, "all"),
+            Target::Client(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl From<ClientId> for Target {
+    fn from(id: ClientId) -> Self {
+        Target::Client(id)
+    }
+}
+
+/// Stops, pauses and reconfigures the speech of any client, for instance
+/// from a settings daemon acting on the user's behalf. Ids too large for
+/// libspeechd fail with `Error::InvalidClientId`.
+#[derive(Clone, Debug)]
+pub struct Supervisor(Connection);
+
+impl Connection {
+    /// A handle for controlling other clients through this connection.
+    pub fn supervisor(&self) -> Supervisor {
+        Supervisor(self.clone())
+    }
+}
+
+impl Supervisor {
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.0
+    }
+
+    /// Every client the server has seen, connected or not.
+    pub fn clients(&self) -> Result<Vec<ClientInfo>, Error> {
+        self.0.list_clients()
+    }
+
+    /// The clients that are still connected.
+    pub fn active_clients(&self) -> Result<Vec<ClientInfo>, Error> {
+        let mut clients = self.clients()?;
+        clients.retain(|c| c.active);
+        Ok(clients)
+    }
+
+    pub fn stop(&self, client: ClientId) -> Result<bool, Error> {
+        Ok(self.0.stop_uid(client.uid()?))
+    }
+
+    pub fn cancel(&self, client: ClientId) -> Result<bool, Error> {
+        Ok(self.0.cancel_uid(client.uid()?))
+    }
+
+    pub fn pause(&self, client: ClientId) -> Result<bool, Error> {
+        Ok(self.0.pause_uid(client.uid()?))
+    }
+
+    pub fn resume(&self, client: ClientId) -> Result<bool, Error> {
+        Ok(self.0.resume_uid(client.uid()?))
+    }
+
+    pub fn set_voice_type(&self, client: ClientId, voice_type: VoiceType) -> Result<bool, Error> {
+        Ok(self.0.set_voice_type_uid(voice_type, client.uid()?))
+    }
+
+    pub fn set_synthesis_voice<S: Into<String>>(
+        &self,
+        client: ClientId,
+        voice_name: S,
+    ) -> Result<bool, Error> {
+        Ok(self.0.set_synthesis_voice_uid(voice_name, client.uid()?))
+    }
+
+    pub fn set_voice_rate(&self, client: ClientId, rate: i32) -> Result<bool, Error> {
+        Ok(self.0.set_voice_rate_uid(rate, client.uid()?))
+    }
+
+    pub fn set_voice_pitch(&self, client: ClientId, pitch: i32) -> Result<bool, Error> {
+        Ok(self.0.set_voice_pitch_uid(pitch, client.uid()?))
+    }
+
+    #[cfg(feature = "0_11")]
+    pub fn set_voice_pitch_range(&self, client: ClientId, pitch_range: i32) -> Result<bool, Error> {
+        self.0.set_voice_pitch_range_uid(pitch_range, client.uid()?)
+    }
+
+    pub fn set_volume(&self, client: ClientId, volume: i32) -> Result<bool, Error> {
+        Ok(self.0.set_volume_uid(volume, client.uid()?))
+    }
+
+    pub fn set_punctuation(
+        &self,
+        client: ClientId,
+        punctuation: Punctuation,
+    ) -> Result<bool, Error> {
+        Ok(self.0.set_punctuation_uid(punctuation, client.uid()?))
+    }
+
+    pub fn set_capital_letters(
+        &self,
+        client: ClientId,
+        capital_letters: CapitalLetters,
+    ) -> Result<bool, Error> {
+        Ok(self
+            .0
+            .set_capital_letters_uid(capital_letters, client.uid()?))
+    }
+
+    pub fn set_spelling(&self, client: ClientId, spelling: bool) -> Result<bool, Error> {
+        Ok(self.0.set_spelling_uid(spelling, client.uid()?))
+    }
+
+    pub fn set_language(&self, client: ClientId, language: &LanguageTag) -> Result<bool, Error> {
+        Ok(self.0.set_language_uid(language, client.uid()?))
+    }
+
+    pub fn set_output_module<S: Into<String>>(
+        &self,
+        client: ClientId,
+        output_module: S,
+    ) -> Result<bool, Error> {
+        Ok(self.0.set_output_module_uid(output_module, client.uid()?))
+    }
+
+    pub fn set_pause_context(&self, client: ClientId, context: u32) -> Result<bool, Error> {
+        Ok(self.0.set_pause_context_uid(context, client.uid()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range_ids_are_refused() {
+        let connection = crate::tests::dead_connection(u64::MAX - 110);
+        let supervisor = connection.supervisor();
+        let beyond_i32 = ClientId(i32::MAX as u64 + 1);
+        assert!(matches!(
+            supervisor.stop(beyond_i32),
+            Err(Error::InvalidClientId(id)) if id == beyond_i32
+        ));
+        let beyond_u32 = ClientId(u32::MAX as u64 + 1);
+        assert!(matches!(
+            supervisor.set_voice_rate(beyond_u32, 10),
+            Err(Error::InvalidClientId(_))
+        ));
+        assert!(!connection.state().lost);
+    }
+}
@@ -0,0 +1,149 @@
+//! Speaking one message with its own voice settings.
+
+use crate::ssml::check;
+use crate::{Connection, DataMode, Error, LanguageTag, Priority, Punctuation, Scoped};
+
+/// A message along with settings that apply to it alone. Settings left
+/// unset keep the connection's current values.
+#[derive(Clone, Debug)]
+pub struct Utterance {
+    text: String,
+    priority: Priority,
+    voice: Option<String>,
+    rate: Option<i32>,
+    pitch: Option<i32>,
+    volume: Option<i32>,
+    language: Option<LanguageTag>,
+    punctuation: Option<Punctuation>,
+    spelling: Option<bool>,
+    data_mode: Option<DataMode>,
+}
+
+impl Utterance {
+    /// A message with `Priority::Text`, the server's default.
+    pub fn new<S: Into<String>>(text: S) -> Self {
+        Self {
+            text: text.into(),
+            priority: Priority::Text,
+            voice: None,
+            rate: None,
+            pitch: None,
+            volume: None,
+            language: None,
+            punctuation: None,
+            spelling: None,
+            data_mode: None,
+        }
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// A synthesis voice, by the name the output module lists it under.
+    pub fn voice<S: Into<String>>(mut self, voice: S) -> Self {
+        self.voice = Some(voice.into());
+        self
+    }
+
+    pub fn rate(mut self, rate: i32) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    pub fn pitch(mut self, pitch: i32) -> Self {
+        self.pitch = Some(pitch);
+        self
+    }
+
+    pub fn volume(mut self, volume: i32) -> Self {
+        self.volume = Some(volume);
+        self
+    }
+
+    pub fn language(mut self, language: LanguageTag) -> Self {
+        self.language = Some(language);
+        self
+    }
+
+    pub fn punctuation(mut self, punctuation: Punctuation) -> Self {
+        self.punctuation = Some(punctuation);
+        self
+    }
+
+    pub fn spelling(mut self, spelling: bool) -> Self {
+        self.spelling = Some(spelling);
+        self
+    }
+
+    /// With `DataMode::SSML` the text must be an SSML document, which is
+    /// checked before anything is sent.
+    pub fn data_mode(mut self, data_mode: DataMode) -> Self {
+        self.data_mode = Some(data_mode);
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Sends the overrides through `scoped`, which undoes them.
+    fn apply(&self, scoped: &mut Scoped) -> Result<(), Error> {
+        if let Some(language) = &self.language {
+            scoped.set_language(language)?;
+        }
+        if let Some(voice) = &self.voice {
+            scoped.set_synthesis_voice(voice.as_str())?;
+        }
+        if let Some(rate) = self.rate {
+            scoped.set_voice_rate(rate)?;
+        }
+        if let Some(pitch) = self.pitch {
+            scoped.set_voice_pitch(pitch)?;
+        }
+        if let Some(volume) = self.volume {
+            scoped.set_volume(volume)?;
+        }
+        if let Some(punctuation) = self.punctuation {
+            scoped.set_punctuation(punctuation)?;
+        }
+        if let Some(spelling) = self.spelling {
+            scoped.set_spelling(spelling)?;
+        }
+        if let Some(data_mode) = self.data_mode {
+            scoped.set_data_mode(data_mode)?;
+        }
+        Ok(())
+    }
+}
+
+impl Connection {
+    /// Speaks an utterance: its settings are applied, the message is sent
+    /// and the previous settings are restored, with no command from another
+    /// thread in between. Returns the message id.
+    ///
+    /// Settings are changed and restored through a `Scoped` guard, so they
+    /// come back to the values recorded on this connection. As there,
+    /// punctuation and spelling can't be asked of the server, so an
+    /// utterance that changes them leaves them changed unless this
+    /// connection set them first.
+    pub fn say_utterance(&self, utterance: &Utterance) -> Result<u64, Error> {
+        if let Some(DataMode::SSML) = utterance.data_mode {
+            check(&utterance.text)?;
+        }
+        let _op = self.serialize();
+        let mut scoped = self.scoped();
+        let msg_id = utterance.apply(&mut scoped).and_then(|_| {
+            self.command(&format!(
+                "SET SELF PRIORITY {}",
+                utterance.priority.as_ssip()
+            ))?;
+            self.speak(&utterance.text)
+        });
+        let restored = scoped.restore();
+        let msg_id = msg_id?;
+        restored?;
+        Ok(msg_id)
+    }
+}
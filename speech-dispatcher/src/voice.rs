@@ -0,0 +1,100 @@
+//! Listing the voices of the current output module.
+
+use std::ffi::CStr;
+#[cfg(feature = "0_11")]
+use std::ffi::CString;
+use std::os::raw::c_char;
+#[cfg(feature = "0_11")]
+use std::ptr;
+
+use speech_dispatcher_sys::*;
+
+#[cfg(feature = "async")]
+use crate::ssip::Reply;
+use crate::{Connection, LanguageTag};
+#[cfg(feature = "0_11")]
+use crate::{Error, Feature};
+
+/// A voice offered by the output module, as listed by
+/// `LIST SYNTHESIS_VOICES`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Voice {
+    /// The name to pass to `set_synthesis_voice`.
+    pub name: String,
+    /// `None` if the module gives no language, or one that isn't a tag.
+    pub language: Option<LanguageTag>,
+    /// The dialect or voice variant, if the module names one.
+    pub variant: Option<String>,
+}
+
+/// Copies a null-terminated `SPDVoice` list and frees it.
+unsafe fn take_voices(list: *mut *mut SPDVoice) -> Vec<Voice> {
+    if list.is_null() {
+        return Vec::new();
+    }
+    let string = |s: *mut c_char| {
+        if s.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+        }
+    };
+    let mut voices = Vec::new();
+    let mut entry = list;
+    while !(*entry).is_null() {
+        let voice = &**entry;
+        voices.push(Voice {
+            name: string(voice.name).unwrap_or_default(),
+            language: string(voice.language).and_then(|l| l.parse().ok()),
+            variant: string(voice.variant).filter(|v| !v.is_empty() && v != "none"),
+        });
+        entry = entry.add(1);
+    }
+    free_spd_voices(list);
+    voices
+}
+
+/// Reads the voices from a `LIST SYNTHESIS_VOICES` reply, one per line as
+/// name, language and variant separated by tabs.
+#[cfg(feature = "async")]
+pub(crate) fn parse_voices(reply: &Reply) -> Vec<Voice> {
+    reply
+        .lines
+        .iter()
+        .map(|line| {
+            let mut fields = line.split('\t').map(str::trim);
+            let mut field = || fields.next().filter(|f| !f.is_empty());
+            Voice {
+                name: field().unwrap_or_default().to_string(),
+                language: field().and_then(|l| l.parse().ok()),
+                variant: field().filter(|v| *v != "none").map(String::from),
+            }
+        })
+        .collect()
+}
+
+impl Connection {
+    /// The voices of the current output module.
+    pub fn list_synthesis_voices(&self) -> Vec<Voice> {
+        let list = self.call_ptr(|c| unsafe { spd_list_synthesis_voices(c) });
+        unsafe { take_voices(list) }
+    }
+
+    /// The voices of the current output module for a language and variant,
+    /// filtered by the server. `None` matches any. Needs libspeechd 0.11,
+    /// and fails with `Error::Unsupported` without it.
+    #[cfg(feature = "0_11")]
+    pub fn list_synthesis_voices_for(
+        &self,
+        language: Option<&LanguageTag>,
+        variant: Option<&str>,
+    ) -> Result<Vec<Voice>, Error> {
+        Feature::FilteredVoiceList.require()?;
+        let cstr = |s: Option<&str>| s.map(|s| CString::new(s).unwrap());
+        let (language, variant) = (cstr(language.map(LanguageTag::as_str)), cstr(variant));
+        let ptr = |s: &Option<CString>| s.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+        let list = self
+            .call_ptr(|c| unsafe { spd_list_synthesis_voices2(c, ptr(&language), ptr(&variant)) });
+        Ok(unsafe { take_voices(list) })
+    }
+}
@@ -0,0 +1,374 @@
+//! Choosing a voice by language, voice type and name, with fallbacks.
+
+use std::fmt;
+
+use crate::{Connection, Error, LanguageTag, Profile, Voice, VoiceType};
+
+/// What to look for in the synthesis voice list, such as "German, female,
+/// preferably a voice whose name contains Anna".
+///
+/// The voice list says nothing of gender, so the voice type is not used to
+/// pick a named voice. When no preferred name matches it is sent instead,
+/// and the output module chooses a voice of that type for the language.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VoiceQuery {
+    language: Option<LanguageTag>,
+    voice_type: Option<VoiceType>,
+    names: Vec<String>,
+}
+
+/// How closely a voice's language matched the one asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LanguageMatch {
+    /// Only the primary language matched, as `de-DE` does for `de-AT`.
+    Primary,
+    /// The voice's language is a prefix of the one asked for, as `de` is
+    /// of `de-AT`.
+    Fallback,
+    Exact,
+}
+
+/// Why a voice was chosen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SelectionReason {
+    /// The voice's name contains this preferred name.
+    Name(String),
+    /// No preferred name matched, so the first voice for the language was
+    /// taken.
+    Language,
+    /// No preferred name matched, so the voice type was sent for the output
+    /// module to choose a voice for the language.
+    VoiceType(VoiceType),
+    /// No voice matched the language, so nothing is sent and the module's
+    /// default voice is kept.
+    ModuleDefault,
+}
+
+impl fmt::Display for SelectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SelectionReason::Name(name) => write!(f, "its name contains {:?}", name),
+            SelectionReason::Language => f.write_str("it is the first voice for the language"),
+            SelectionReason::VoiceType(voice_type) => {
+                write!(
+                    f,
+                    "the module picks a {} voice for the language",
+                    voice_type
+                )
+            }
+            SelectionReason::ModuleDefault => f.write_str("no voice matched the language"),
+        }
+    }
+}
+
+/// The outcome of resolving a `VoiceQuery`. Settings left `None` are not
+/// changed when it is applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoiceSelection {
+    /// The voice chosen by name, or `None` when the choice is left to the
+    /// output module.
+    pub voice: Option<Voice>,
+    /// The language sent to the server, as the voice list gives it.
+    pub language: Option<LanguageTag>,
+    pub language_match: Option<LanguageMatch>,
+    pub voice_type: Option<VoiceType>,
+    pub reason: SelectionReason,
+}
+
+impl VoiceSelection {
+    /// The settings that select this voice, for applying elsewhere, such
+    /// as to another client through a `Supervisor`. Empty when the module
+    /// default is kept.
+    pub fn profile(&self) -> Profile {
+        if self.reason == SelectionReason::ModuleDefault {
+            return Profile::default();
+        }
+        Profile {
+            language: self.language.clone(),
+            voice_type: self.voice_type,
+            synthesis_voice: self.voice.as_ref().map(|v| v.name.clone()),
+            ..Profile::default()
+        }
+    }
+}
+
+impl fmt::Display for VoiceSelection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.voice {
+            Some(voice) => write!(f, "voice {:?}", voice.name)?,
+            None => f.write_str("no named voice")?,
+        }
+        write!(f, ", because {}", self.reason)
+    }
+}
+
+/// How well `offered` serves `wanted`, ranked so that better matches
+/// compare greater.
+fn language_match(wanted: &[&str], offered: &LanguageTag) -> Option<(LanguageMatch, usize)> {
+    let offered: Vec<&str> = offered.subtags().collect();
+    if offered == wanted {
+        Some((LanguageMatch::Exact, offered.len()))
+    } else if wanted.starts_with(&offered) {
+        Some((LanguageMatch::Fallback, offered.len()))
+    } else if offered.first() == wanted.first() {
+        Some((LanguageMatch::Primary, 1))
+    } else {
+        None
+    }
+}
+
+impl VoiceQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A BCP 47 language tag such as `de-AT`. Voices for `de` are used if
+    /// none is for Austrian German, and then those for any other `de-*`.
+    pub fn language(mut self, language: LanguageTag) -> Self {
+        self.language = Some(language);
+        self
+    }
+
+    pub fn voice_type(mut self, voice_type: VoiceType) -> Self {
+        self.voice_type = Some(voice_type);
+        self
+    }
+
+    /// Prefers voices whose name contains `name`, ignoring case. Earlier
+    /// preferences win over later ones.
+    pub fn prefer_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Chooses among `voices` without talking to the server.
+    pub fn resolve(&self, voices: &[Voice]) -> VoiceSelection {
+        let (candidates, language_match) = match &self.language {
+            Some(language) => {
+                let wanted: Vec<&str> = language.subtags().collect();
+                let ranked: Vec<_> = voices
+                    .iter()
+                    .filter_map(|v| {
+                        let m = language_match(&wanted, v.language.as_ref()?)?;
+                        Some((m, v))
+                    })
+                    .collect();
+                let best = ranked.iter().map(|(m, _)| *m).max();
+                let candidates = ranked
+                    .into_iter()
+                    .filter(|(m, _)| Some(*m) == best)
+                    .map(|(_, v)| v)
+                    .collect();
+                (candidates, best.map(|(m, _)| m))
+            }
+            None => (voices.iter().collect::<Vec<_>>(), None),
+        };
+
+        let mut selection = VoiceSelection {
+            voice: None,
+            language: candidates.first().and_then(|v| v.language.clone()),
+            language_match,
+            voice_type: None,
+            reason: SelectionReason::ModuleDefault,
+        };
+        if self.language.is_some() && candidates.is_empty() {
+            return selection;
+        }
+        if self.language.is_none() {
+            selection.language = None;
+        }
+
+        let named = self.names.iter().find_map(|name| {
+            let lower = name.to_lowercase();
+            candidates
+                .iter()
+                .find(|v| v.name.to_lowercase().contains(&lower))
+                .map(|v| (name, *v))
+        });
+        if let Some((name, voice)) = named {
+            selection.language = voice.language.clone();
+            selection.voice = Some(voice.clone());
+            selection.reason = SelectionReason::Name(name.clone());
+        } else if let Some(voice_type) = self.voice_type {
+            selection.voice_type = Some(voice_type);
+            selection.reason = SelectionReason::VoiceType(voice_type);
+        } else if let Some(voice) = candidates.first().filter(|_| self.language.is_some()) {
+            selection.voice = Some((*voice).clone());
+            selection.reason = SelectionReason::Language;
+        }
+        selection
+    }
+}
+
+impl Connection {
+    /// Resolves `query` against the voices of the current output module and
+    /// applies the result.
+    pub fn select_voice(&self, query: &VoiceQuery) -> Result<VoiceSelection, Error> {
+        let selection = query.resolve(&self.list_synthesis_voices());
+        self.apply_profile(&selection.profile())?;
+        Ok(selection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(s: &str) -> LanguageTag {
+        s.parse().unwrap()
+    }
+
+    fn voice(name: &str, language: Option<&str>) -> Voice {
+        Voice {
+            name: name.to_string(),
+            language: language.map(tag),
+            variant: None,
+        }
+    }
+
+    fn voices() -> Vec<Voice> {
+        vec![
+            voice("Anna", Some("de-DE")),
+            voice("Hans", Some("de")),
+            voice("Klaus", Some("de-AT")),
+            voice("Petra", Some("de-DE")),
+            voice("Emma", Some("en-GB")),
+            voice("Beep", None),
+        ]
+    }
+
+    fn chosen(selection: &VoiceSelection) -> Option<&str> {
+        selection.voice.as_ref().map(|v| v.name.as_str())
+    }
+
+    #[test]
+    fn closer_languages_win() {
+        let voices = voices();
+        let exact = VoiceQuery::new().language(tag("de-AT")).resolve(&voices);
+        assert_eq!(chosen(&exact), Some("Klaus"));
+        assert_eq!(exact.language_match, Some(LanguageMatch::Exact));
+        assert_eq!(exact.reason, SelectionReason::Language);
+
+        let fallback = VoiceQuery::new().language(tag("de-CH")).resolve(&voices);
+        assert_eq!(chosen(&fallback), Some("Hans"));
+        assert_eq!(fallback.language, Some(tag("de")));
+        assert_eq!(fallback.language_match, Some(LanguageMatch::Fallback));
+
+        let primary = VoiceQuery::new()
+            .language(tag("de-CH"))
+            .resolve(&voices[..1]);
+        assert_eq!(chosen(&primary), Some("Anna"));
+        assert_eq!(primary.language_match, Some(LanguageMatch::Primary));
+    }
+
+    #[test]
+    fn earlier_names_win_ignoring_case() {
+        let selection = VoiceQuery::new()
+            .language(tag("de-DE"))
+            .prefer_name("Klaus")
+            .prefer_name("PET")
+            .prefer_name("anna")
+            .resolve(&voices());
+        assert_eq!(chosen(&selection), Some("Petra"));
+        assert_eq!(selection.language, Some(tag("de-DE")));
+        assert_eq!(selection.reason, SelectionReason::Name("PET".to_string()));
+    }
+
+    #[test]
+    fn voice_type_is_sent_when_no_name_matches() {
+        let selection = VoiceQuery::new()
+            .language(tag("de-DE"))
+            .voice_type(VoiceType::Female1)
+            .prefer_name("Emma")
+            .resolve(&voices());
+        assert_eq!(selection.voice, None);
+        assert_eq!(selection.language, Some(tag("de-DE")));
+        assert_eq!(selection.voice_type, Some(VoiceType::Female1));
+        assert_eq!(
+            selection.reason,
+            SelectionReason::VoiceType(VoiceType::Female1)
+        );
+    }
+
+    #[test]
+    fn unknown_languages_keep_the_module_default() {
+        let selection = VoiceQuery::new()
+            .language(tag("fr"))
+            .voice_type(VoiceType::Male2)
+            .prefer_name("Anna")
+            .resolve(&voices());
+        assert_eq!(selection.voice, None);
+        assert_eq!(selection.language, None);
+        assert_eq!(selection.language_match, None);
+        assert_eq!(selection.voice_type, None);
+        assert_eq!(selection.reason, SelectionReason::ModuleDefault);
+        assert_eq!(selection.profile(), Profile::default());
+    }
+
+    #[test]
+    fn module_default_applies_nothing() {
+        // Any command would fail on this connection.
+        let connection = crate::tests::dead_connection(u64::MAX - 120);
+        let query = VoiceQuery::new()
+            .language(tag("fr"))
+            .voice_type(VoiceType::Male2);
+        let selection = connection.select_voice(&query).unwrap();
+        assert_eq!(selection.reason, SelectionReason::ModuleDefault);
+        assert_eq!(
+            Profile::from_settings(&connection.settings()),
+            Profile::default()
+        );
+    }
+
+    #[test]
+    fn without_a_language_only_names_pick_a_voice() {
+        let voices = voices();
+        let named = VoiceQuery::new().prefer_name("emma").resolve(&voices);
+        assert_eq!(chosen(&named), Some("Emma"));
+        assert_eq!(named.language, Some(tag("en-GB")));
+        assert_eq!(named.language_match, None);
+
+        let typed = VoiceQuery::new()
+            .voice_type(VoiceType::ChildMale)
+            .resolve(&voices);
+        assert_eq!(typed.voice, None);
+        assert_eq!(typed.language, None);
+        assert_eq!(
+            typed.reason,
+            SelectionReason::VoiceType(VoiceType::ChildMale)
+        );
+
+        let empty = VoiceQuery::new().resolve(&voices);
+        assert_eq!(empty.voice, None);
+        assert_eq!(empty.reason, SelectionReason::ModuleDefault);
+    }
+
+    #[test]
+    fn profile_selects_the_voice() {
+        let named = VoiceQuery::new()
+            .language(tag("de-AT"))
+            .prefer_name("klaus")
+            .resolve(&voices());
+        let profile = named.profile();
+        assert_eq!(profile.synthesis_voice.as_deref(), Some("Klaus"));
+        assert_eq!(profile.language, Some(tag("de-AT")));
+        assert_eq!(profile.voice_type, None);
+        assert_eq!(
+            named.to_string(),
+            "voice \"Klaus\", because its name contains \"klaus\""
+        );
+
+        let typed = VoiceQuery::new()
+            .language(tag("en"))
+            .voice_type(VoiceType::Female2)
+            .resolve(&voices());
+        let profile = typed.profile();
+        assert_eq!(profile.synthesis_voice, None);
+        assert_eq!(profile.language, Some(tag("en-GB")));
+        assert_eq!(profile.voice_type, Some(VoiceType::Female2));
+        assert_eq!(
+            typed.to_string(),
+            "no named voice, because the module picks a female2 voice for the language"
+        );
+    }
+}
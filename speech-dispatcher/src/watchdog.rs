@@ -0,0 +1,87 @@
+//! Command timeouts and keepalive pings.
+
+use std::os::raw::c_int;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Connection, Inner};
+
+#[derive(Debug, Default)]
+struct Deadline {
+    armed: Option<(Instant, c_int)>,
+    fired: bool,
+    stop: bool,
+}
+
+/// A thread that shuts down the socket of a command that takes too long,
+/// which makes the blocked libspeechd call return.
+#[derive(Debug)]
+pub(crate) struct Watchdog(Arc<(Mutex<Deadline>, Condvar)>);
+
+impl Watchdog {
+    pub fn new() -> Self {
+        let shared = Arc::new((Mutex::new(Deadline::default()), Condvar::new()));
+        let watched = shared.clone();
+        thread::spawn(move || {
+            let (lock, cvar) = &*watched;
+            let mut deadline = lock.lock().unwrap();
+            while !deadline.stop {
+                match deadline.armed {
+                    None => deadline = cvar.wait(deadline).unwrap(),
+                    Some((at, fd)) => {
+                        let now = Instant::now();
+                        if now >= at {
+                            unsafe { libc::shutdown(fd, libc::SHUT_RDWR) };
+                            deadline.armed = None;
+                            deadline.fired = true;
+                        } else {
+                            deadline = cvar.wait_timeout(deadline, at - now).unwrap().0;
+                        }
+                    }
+                }
+            }
+        });
+        Self(shared)
+    }
+
+    pub fn arm(&self, fd: c_int, timeout: Duration) {
+        let (lock, cvar) = &*self.0;
+        let mut deadline = lock.lock().unwrap();
+        deadline.armed = Some((Instant::now() + timeout, fd));
+        deadline.fired = false;
+        cvar.notify_one();
+    }
+
+    /// Returns whether the deadline passed before the command finished.
+    pub fn disarm(&self) -> bool {
+        let (lock, _) = &*self.0;
+        let mut deadline = lock.lock().unwrap();
+        deadline.armed = None;
+        deadline.fired
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.0;
+        lock.lock().unwrap().stop = true;
+        cvar.notify_one();
+    }
+}
+
+/// Pings the server every `interval` until the connection is dropped or the
+/// keepalive is changed, which bumps `generation`.
+pub(crate) fn spawn_keepalive(inner: Weak<Inner>, interval: Duration, generation: u64) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let connection = match inner.upgrade() {
+            Some(inner) => Connection(inner),
+            None => return,
+        };
+        if connection.state().keepalive_generation != generation {
+            return;
+        }
+        connection.ping(interval);
+    });
+}